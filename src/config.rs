@@ -0,0 +1,183 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+use x11rb::rust_connection::RustConnection;
+
+use crate::{load_icon, parse_geometry, try_get_atom, Cli, IconOptions, WindowSize, WindowType};
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+	#[serde(default)]
+	pub profiles: BTreeMap<String, Profile>,
+}
+
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+	pub icon: Option<PathBuf>,
+	pub size: Option<WindowSize>,
+	#[serde(rename = "type")]
+	pub win_type: Option<WindowType>,
+	pub geometry: Option<String>,
+	pub above: Option<bool>,
+	pub no_decoration: Option<bool>,
+	pub no_taskbar_icon: Option<bool>,
+	pub shade: Option<bool>,
+}
+
+impl Config {
+	pub fn load(path: &Path) -> Result<Self>
+	{
+		let text = fs::read_to_string(path)
+			.map_err(|e| anyhow!("Failed read config file {}: {e}", path.display()))?;
+		toml::from_str(&text)
+			.map_err(|e| anyhow!("Failed parse config file {}: {e}", path.display()))
+	}
+
+	pub fn profile(&self, name: &str) -> Result<&Profile>
+	{
+		self.profiles.get(name).ok_or_else(|| {
+			let names: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+			anyhow!("Profile not found: {name}, available profiles: [{}]", names.join(", "))
+		})
+	}
+
+	/// validate every profile, atoms are only checked when a connection is given
+	pub fn check(&self, conn: Option<&RustConnection>) -> Vec<String>
+	{
+		let mut errors = vec![];
+		for (name, profile) in &self.profiles {
+			for error in profile.check(conn) {
+				errors.push(format!("profile \"{name}\": {error}"));
+			}
+		}
+		errors
+	}
+}
+
+impl Profile {
+	/// fill in cli options that not specified from command line
+	pub fn merge(&self, cli: &mut Cli)
+	{
+		if cli.icon.is_empty() && cli.icon_name.is_none() && cli.icon_1x.is_none()
+			&& cli.icon_raw.is_none() && !cli.auto_icon && !cli.no_icon && !cli.remove_icon {
+			if let Some(icon) = &self.icon {
+				cli.icon.push(icon.clone());
+			}
+		}
+		if cli.size.is_none() {
+			cli.size.clone_from(&self.size);
+		}
+		if cli.win_type.is_empty() {
+			cli.win_type.extend(self.win_type.clone());
+		}
+		if cli.geometry.is_none() {
+			cli.geometry.clone_from(&self.geometry);
+		}
+		if !cli.below {
+			cli.above |= self.above.unwrap_or(false);
+		}
+		cli.no_decoration |= self.no_decoration.unwrap_or(false);
+		cli.no_taskbar_icon |= self.no_taskbar_icon.unwrap_or(false);
+		cli.shade |= self.shade.unwrap_or(false);
+	}
+
+	fn check(&self, conn: Option<&RustConnection>) -> Vec<String>
+	{
+		let mut errors = vec![];
+		if let Some(icon) = &self.icon {
+			if !icon.exists() {
+				errors.push(format!("icon file not exists: {}", icon.display()));
+			} else if let Err(e) = load_icon(icon, &IconOptions::default()) {
+				errors.push(format!("invalid icon file {}: {e}", icon.display()));
+			}
+		}
+		if let Some(geometry) = &self.geometry {
+			if let Err(e) = parse_geometry(geometry) {
+				errors.push(e.to_string());
+			}
+		}
+		if let Some(conn) = conn {
+			for atom_name in self.atom_names() {
+				match try_get_atom(conn, atom_name) {
+					Ok(Some(_)) => {}
+					Ok(None) => errors.push(format!("atom not exists: {atom_name}")),
+					Err(e) => errors.push(format!("failed check atom {atom_name}: {e}")),
+				}
+			}
+		}
+		errors
+	}
+
+	fn atom_names(&self) -> Vec<&'static str>
+	{
+		let mut names = vec![];
+		if let Some(size) = &self.size {
+			names.extend_from_slice(size.atom_names());
+		}
+		if let Some(win_type) = &self.win_type {
+			names.push("_NET_WM_WINDOW_TYPE");
+			names.push(win_type.as_str());
+		}
+		if self.above == Some(true) {
+			names.push("_NET_WM_STATE_ABOVE");
+		}
+		if self.no_decoration == Some(true) {
+			names.push("_MOTIF_WM_HINTS");
+		}
+		if self.no_taskbar_icon == Some(true) {
+			names.push("_NET_WM_STATE_SKIP_TASKBAR");
+		}
+		if self.shade == Some(true) {
+			names.push("_NET_WM_STATE_SHADED");
+		}
+		names
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use clap::Parser;
+	use crate::config::{Config, Profile};
+	use crate::{Cli, WindowSize, WindowType};
+
+	#[test]
+	fn test_check_config()
+	{
+		let config: Config = toml::from_str(r#"
+			[profiles.clock]
+			geometry = "150x30-250+0"
+			size = "max"
+			type = "dock"
+			above = true
+
+			[profiles.broken]
+			geometry = "150x30x10"
+			icon = "/not/exists.png"
+		"#).unwrap();
+		let clock = &config.profiles["clock"];
+		assert!(matches!(clock.size, Some(WindowSize::Max)));
+		assert!(matches!(clock.win_type, Some(WindowType::Dock)));
+		assert_eq!(clock.above, Some(true));
+		let errors = config.check(None);
+		assert_eq!(errors.len(), 2);
+		assert!(errors.iter().all(|e| e.starts_with("profile \"broken\"")));
+		assert!(config.profile("clock").is_ok());
+		assert!(config.profile("missing").is_err());
+	}
+
+	#[test]
+	fn test_merge_icon()
+	{
+		let profile: Profile = toml::from_str(r#"icon = "/tmp/icon.png""#).unwrap();
+		let mut cli = Cli::parse_from(["xicon", "-c", "xterm"]);
+		profile.merge(&mut cli);
+		assert_eq!(cli.icon.len(), 1);
+		let mut cli = Cli::parse_from(["xicon", "--remove-icon", "-c", "xterm"]);
+		profile.merge(&mut cli);
+		assert!(cli.icon.is_empty());
+	}
+}
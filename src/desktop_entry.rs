@@ -0,0 +1,89 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::icon_theme::{data_dirs, read_ini};
+
+const SECTION: &str = "Desktop Entry";
+
+/// Icon key of the desktop entry whose Exec or TryExec matches command,
+/// when multiple entries match, the one with StartupWMClass equals wm_class wins
+pub fn find_icon(command: &str, wm_class: Option<&str>) -> Option<String>
+{
+	let command = basename(command);
+	let mut found = None;
+	for dir in data_dirs() {
+		let mut files = vec![];
+		desktop_files(&dir.join("applications"), &mut files);
+		for file in files {
+			let Some(mut ini) = read_ini(&file) else {
+				continue;
+			};
+			let Some(mut entry) = ini.remove(SECTION) else {
+				continue;
+			};
+			let matched = ["Exec", "TryExec"].iter()
+				.filter_map(|key| entry.get(*key))
+				.any(|exec| exec_program(exec).is_some_and(|program| basename(program) == command));
+			if !matched {
+				continue;
+			}
+			let Some(icon) = entry.remove("Icon") else {
+				continue;
+			};
+			match wm_class {
+				Some(wm_class) if entry.get("StartupWMClass").is_some_and(|c| c == wm_class) =>
+					return Some(icon),
+				_ => if found.is_none() {
+					found = Some(icon);
+				}
+			}
+		}
+	}
+	found
+}
+
+fn desktop_files(dir: &Path, files: &mut Vec<PathBuf>)
+{
+	let Ok(entries) = fs::read_dir(dir) else {
+		return;
+	};
+	let mut paths: Vec<PathBuf> = entries
+		.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+		.collect();
+	paths.sort();
+	for path in paths {
+		if path.is_dir() {
+			desktop_files(&path, files);
+		} else if path.extension().is_some_and(|ext| ext == "desktop") {
+			files.push(path);
+		}
+	}
+}
+
+/// program of the Exec value, with env and variable assignments skipped
+fn exec_program(exec: &str) -> Option<&str>
+{
+	exec.split_whitespace()
+		.map(|token| token.trim_matches('"'))
+		.find(|token| *token != "env" && !token.contains('='))
+}
+
+#[inline]
+fn basename(path: &str) -> &str
+{
+	path.rsplit('/').next().unwrap_or(path)
+}
+
+#[cfg(test)]
+mod test {
+	use crate::desktop_entry::{basename, exec_program};
+
+	#[test]
+	fn test_exec_program()
+	{
+		assert_eq!(exec_program("firefox %u"), Some("firefox"));
+		assert_eq!(exec_program("\"/opt/app/bin/app\" --flag"), Some("/opt/app/bin/app"));
+		assert_eq!(exec_program("env LANG=C xterm"), Some("xterm"));
+		assert_eq!(basename("/usr/bin/xterm"), "xterm");
+	}
+}
@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, Result};
+
+const FALLBACK_THEME: &str = "hicolor";
+#[cfg(feature = "svg")]
+const EXTENSIONS: [&str; 3] = ["png", "svg", "xpm"];
+#[cfg(not(feature = "svg"))]
+const EXTENSIONS: [&str; 2] = ["png", "xpm"];
+
+/// ini file content, section name => (key => value)
+type Ini = HashMap<String, HashMap<String, String>>;
+
+/// resolve icon name to the largest icon file in theme, its parents and hicolor
+pub fn lookup(name: &str, theme: Option<&str>) -> Result<PathBuf>
+{
+	let base_dirs = base_dirs();
+	let theme = match theme {
+		Some(theme) => theme.to_owned(),
+		None => current_theme().unwrap_or_else(|| FALLBACK_THEME.to_owned()),
+	};
+	let mut searched = vec![];
+	let mut themes = vec![theme];
+	let mut i = 0;
+	while i < themes.len() {
+		let theme = themes[i].clone();
+		i += 1;
+		let mut best: Option<(u32, PathBuf)> = None;
+		for base_dir in &base_dirs {
+			let theme_dir = base_dir.join(&theme);
+			let Some(index) = read_ini(&theme_dir.join("index.theme")) else {
+				continue;
+			};
+			searched.push(theme_dir.clone());
+			let Some(theme_section) = index.get("Icon Theme") else {
+				continue;
+			};
+			if let Some(inherits) = theme_section.get("Inherits") {
+				for parent in inherits.split(',').map(str::trim) {
+					if !parent.is_empty() && !themes.iter().any(|t| t == parent) {
+						themes.push(parent.to_owned());
+					}
+				}
+			}
+			let Some(directories) = theme_section.get("Directories") else {
+				continue;
+			};
+			for directory in directories.split(',').map(str::trim) {
+				let size = directory_size(index.get(directory));
+				if best.as_ref().is_some_and(|(best_size, _)| *best_size >= size) {
+					continue;
+				}
+				if let Some(path) = find_file(&theme_dir.join(directory), name) {
+					best = Some((size, path));
+				}
+			}
+		}
+		if let Some((_, path)) = best {
+			return Ok(path);
+		}
+		// hicolor is always the last one
+		if i == themes.len() && !themes.iter().any(|t| t == FALLBACK_THEME) {
+			themes.push(FALLBACK_THEME.to_owned());
+		}
+	}
+	for dir in pixmaps_dirs() {
+		if let Some(path) = find_file(&dir, name) {
+			return Ok(path);
+		}
+		searched.push(dir);
+	}
+	let searched: Vec<String> = searched.iter()
+		.map(|dir| dir.display().to_string())
+		.collect();
+	Err(anyhow!("Icon not found: {name}, searched: [{}]", searched.join(", ")))
+}
+
+#[inline]
+fn find_file(dir: &Path, name: &str) -> Option<PathBuf>
+{
+	EXTENSIONS.iter()
+		.map(|ext| dir.join(format!("{name}.{ext}")))
+		.find(|path| path.is_file())
+}
+
+/// scalable directories use its max size
+#[inline]
+fn directory_size(section: Option<&HashMap<String, String>>) -> u32
+{
+	let Some(section) = section else {
+		return 0;
+	};
+	let get = |key: &str| section.get(key).and_then(|v| v.parse::<u32>().ok());
+	let size = get("Size").unwrap_or(0);
+	let scale = get("Scale").unwrap_or(1);
+	let size = if section.get("Type").is_some_and(|t| t == "Scalable") {
+		get("MaxSize").unwrap_or(size)
+	} else {
+		size
+	};
+	size * scale
+}
+
+fn base_dirs() -> Vec<PathBuf>
+{
+	let mut dirs = vec![];
+	if let Some(home) = env::var_os("HOME") {
+		dirs.push(PathBuf::from(home).join(".icons"));
+	}
+	for data_dir in data_dirs() {
+		dirs.push(data_dir.join("icons"));
+	}
+	dirs
+}
+
+#[inline]
+fn pixmaps_dirs() -> Vec<PathBuf>
+{
+	vec![PathBuf::from("/usr/share/pixmaps")]
+}
+
+/// $XDG_DATA_HOME and $XDG_DATA_DIRS, with defaults from the base directory spec
+pub fn data_dirs() -> Vec<PathBuf>
+{
+	let mut dirs = vec![];
+	match env::var_os("XDG_DATA_HOME") {
+		Some(dir) if !dir.is_empty() => dirs.push(PathBuf::from(dir)),
+		_ => if let Some(home) = env::var_os("HOME") {
+			dirs.push(PathBuf::from(home).join(".local/share"));
+		}
+	}
+	let data_dirs = env::var("XDG_DATA_DIRS")
+		.ok()
+		.filter(|dirs| !dirs.is_empty())
+		.unwrap_or_else(|| "/usr/local/share:/usr/share".to_owned());
+	for dir in data_dirs.split(':').filter(|dir| !dir.is_empty()) {
+		dirs.push(PathBuf::from(dir));
+	}
+	dirs
+}
+
+fn config_home() -> Option<PathBuf>
+{
+	match env::var_os("XDG_CONFIG_HOME") {
+		Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+		_ => env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")),
+	}
+}
+
+/// theme name from gtk or kde settings
+fn current_theme() -> Option<String>
+{
+	let config_home = config_home()?;
+	for (file, section, key) in [
+		("gtk-3.0/settings.ini", "Settings", "gtk-icon-theme-name"),
+		("gtk-4.0/settings.ini", "Settings", "gtk-icon-theme-name"),
+		("kdeglobals", "Icons", "Theme"),
+	] {
+		if let Some(theme) = read_ini(&config_home.join(file))
+			.and_then(|mut ini| ini.remove(section))
+			.and_then(|mut section| section.remove(key)) {
+			return Some(theme);
+		}
+	}
+	None
+}
+
+pub fn read_ini(path: &Path) -> Option<Ini>
+{
+	let text = fs::read_to_string(path).ok()?;
+	Some(parse_ini(&text))
+}
+
+fn parse_ini(text: &str) -> Ini
+{
+	let mut ini = Ini::new();
+	let mut section = String::new();
+	for line in text.lines() {
+		let line = line.trim();
+		if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+			continue;
+		}
+		if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+			section = name.to_owned();
+		} else if let Some((key, value)) = line.split_once('=') {
+			ini.entry(section.clone())
+				.or_default()
+				.insert(key.trim().to_owned(), value.trim().to_owned());
+		}
+	}
+	ini
+}
+
+#[cfg(test)]
+mod test {
+	use crate::icon_theme::{directory_size, parse_ini};
+
+	#[test]
+	fn test_parse_index_theme()
+	{
+		let ini = parse_ini("[Icon Theme]
+Name=Test
+Inherits=Adwaita,hicolor
+Directories=16x16/apps,scalable/apps
+
+# comment
+[16x16/apps]
+Size=16
+Type=Fixed
+
+[scalable/apps]
+Size=64
+MinSize=8
+MaxSize=512
+Type=Scalable
+");
+		assert_eq!(ini["Icon Theme"]["Inherits"], "Adwaita,hicolor");
+		assert_eq!(directory_size(ini.get("16x16/apps")), 16);
+		assert_eq!(directory_size(ini.get("scalable/apps")), 512);
+		assert_eq!(directory_size(ini.get("missing")), 0);
+	}
+}
@@ -1,49 +1,111 @@
+mod config;
+mod desktop_entry;
+mod icon_theme;
+mod script;
+mod xpm;
+
 use std::borrow::Cow;
+use std::env;
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
-use std::process::Command;
-use std::time::SystemTime;
+use std::fs::OpenOptions;
+use std::io::{self, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Child, Command};
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime};
 use anyhow::{anyhow, Result};
-use clap::Parser;
+use clap::{CommandFactory, Parser};
+use clap::error::ErrorKind;
 use fork::Fork;
+use image::{DynamicImage, RgbaImage};
+use image::imageops::FilterType;
 use regex::Regex;
-use x11rb::connection::Connection;
+use serde::Deserialize;
+use x11rb::connection::{Connection, RequestConnection};
+use x11rb::errors::ReplyError;
+use x11rb::properties::{WmHints, WmSizeHints, WmSizeHintsSpecification};
+use x11rb::x11_utils::X11Error;
 use x11rb::protocol::Event;
-use x11rb::protocol::xproto::{Atom, AtomEnum, ChangeWindowAttributesAux, ClientMessageEvent, ConfigureWindowAux, ConnectionExt, EventMask, PropMode, Screen, Window};
+use x11rb::protocol::randr::{ConnectionExt as RandrConnectionExt, MonitorInfo};
+use x11rb::protocol::xproto::{Atom, AtomEnum, ChangeWindowAttributesAux, ClientMessageEvent, CloseDown, ConfigureWindowAux, ConnectionExt, CreateGCAux, EventMask, Format, GetPropertyReply, Gravity, ImageFormat, ImageOrder, InputFocus, MapState, Pixmap, PropMode, Property, Screen, StackMode, Window};
 use x11rb::rust_connection::RustConnection;
+use crate::config::Config;
+
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+static START: OnceLock<SystemTime> = OnceLock::new();
+
+/// print to stderr with elapsed time since xicon started, only when --verbose
+macro_rules! verbose {
+	($($arg:tt)*) => {
+		if is_verbose() {
+			eprintln!("[{:.3}s] {}", elapsed().as_secs_f64(), format_args!($($arg)*));
+		}
+	};
+}
 
 #[derive(Clone, Debug)]
 enum WindowMatchProperty {
+	/// any component of WM_CLASS equals
 	Class(String),
+	/// instance or class of WM_CLASS equals, same as Class
+	ClassAny(String),
+	/// instance of WM_CLASS equals
+	Instance(String),
 	Name(String),
+	Role(String),
+	/// full path or basename of /proc/<pid>/exe
+	Exe(String),
+	/// _NET_WM_NAME or WM_NAME starts with
+	NameStartsWith(String),
+	/// _NET_WM_NAME or WM_NAME ends with
+	NameEndsWith(String),
 }
 
-impl<'a> From<&'a str> for WindowMatchProperty {
-	fn from(value: &'a str) -> Self
+impl<'a> TryFrom<&'a str> for WindowMatchProperty {
+	type Error = String;
+
+	fn try_from(value: &'a str) -> Result<Self, Self::Error>
 	{
-		let re = Regex::new(r"^((class)|(name))=(.+)$").unwrap();
+		let re = Regex::new(r"^(class|class_any|instance|name|name_startswith|name_endswith|role|exe)=(.+)$").unwrap();
 		let captures = re.captures(value)
-			.unwrap_or_else(|| panic!("Invalid match property: {value}"));
-		if let (Some(type_), Some(name)) = (captures.get(1), captures.get(4)) {
-			if type_.as_str() == "class" {
-				WindowMatchProperty::Class(name.as_str().to_owned())
-			} else {
-				WindowMatchProperty::Name(name.as_str().to_owned())
+			.ok_or_else(|| format!("invalid match property: {value}, expect <class|class_any|instance|name|name_startswith|name_endswith|role|exe>=<property value>"))?;
+		if let (Some(type_), Some(name)) = (captures.get(1), captures.get(2)) {
+			let name = name.as_str().to_owned();
+			match type_.as_str() {
+				"class" => Ok(WindowMatchProperty::Class(name)),
+				"class_any" => Ok(WindowMatchProperty::ClassAny(name)),
+				"instance" => Ok(WindowMatchProperty::Instance(name)),
+				"role" => Ok(WindowMatchProperty::Role(name)),
+				"exe" => Ok(WindowMatchProperty::Exe(name)),
+				"name_startswith" => Ok(WindowMatchProperty::NameStartsWith(name)),
+				"name_endswith" => Ok(WindowMatchProperty::NameEndsWith(name)),
+				_ => Ok(WindowMatchProperty::Name(name)),
 			}
 		} else {
-			panic!("Invalid match property: {value}")
+			Err(format!("invalid match property: {value}"))
 		}
 	}
 }
 
-#[derive(clap::ValueEnum, Clone, Debug)]
+#[derive(clap::ValueEnum, Deserialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
 enum WindowSize {
 	Max,
+	MaxVert,
+	MaxHorz,
+	// iconify by WM_CHANGE_STATE
 	Min,
+	// add _NET_WM_STATE_HIDDEN as old versions did
+	MinLegacy,
 	Fullscreen,
 }
 
-#[derive(clap::ValueEnum, Clone, Debug)]
+#[derive(clap::ValueEnum, Deserialize, Clone, Debug)]
+#[serde(rename_all = "kebab-case")]
 enum WindowType {
 	Desktop,
 	Dock,
@@ -55,9 +117,150 @@ enum WindowType {
 	Normal,
 }
 
+impl WindowSize {
+	fn atom_names(&self) -> &'static [&'static str]
+	{
+		match self {
+			WindowSize::Max => &["_NET_WM_STATE_MAXIMIZED_VERT", "_NET_WM_STATE_MAXIMIZED_HORZ"],
+			WindowSize::MaxVert => &["_NET_WM_STATE_MAXIMIZED_VERT"],
+			WindowSize::MaxHorz => &["_NET_WM_STATE_MAXIMIZED_HORZ"],
+			WindowSize::Min => &[],
+			WindowSize::MinLegacy => &["_NET_WM_STATE_HIDDEN"],
+			WindowSize::Fullscreen => &["_NET_WM_STATE_FULLSCREEN"],
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Desktop {
+	Index(u32),
+	Current,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Length {
+	Pixel(u32),
+	Percent(u32),
+}
+
+impl Length {
+	#[inline]
+	fn resolve(&self, total: u32) -> u32
+	{
+		match self {
+			Length::Pixel(value) => *value,
+			Length::Percent(value) => total * value / 100,
+		}
+	}
+}
+
+/// rectangle that geometry is relative to
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Area {
+	x: i32,
+	y: i32,
+	width: u32,
+	height: u32,
+}
+
+impl From<&Screen> for Area {
+	fn from(screen: &Screen) -> Self
+	{
+		Area {
+			x: 0,
+			y: 0,
+			width: screen.width_in_pixels as u32,
+			height: screen.height_in_pixels as u32,
+		}
+	}
+}
+
+impl From<&MonitorInfo> for Area {
+	fn from(info: &MonitorInfo) -> Self
+	{
+		Area {
+			x: info.x as i32,
+			y: info.y as i32,
+			width: info.width as u32,
+			height: info.height as u32,
+		}
+	}
+}
+
+/// position on one axis
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Offset {
+	/// from left or top edge
+	Start(Length),
+	/// from right or bottom edge
+	End(Length),
+	Center,
+}
+
+impl Offset {
+	#[inline]
+	fn map(self, f: impl Fn(Length) -> Length) -> Self
+	{
+		match self {
+			Offset::Start(length) => Offset::Start(f(length)),
+			Offset::End(length) => Offset::End(f(length)),
+			Offset::Center => Offset::Center,
+		}
+	}
+
+	/// window size is needed to resolve
+	#[inline]
+	fn needs_size(&self) -> bool
+	{
+		!matches!(self, Offset::Start(_))
+	}
+
+	/// position of window with size in range of start and total length
+	fn resolve(&self, start: i32, total: u32, size: u32) -> i32
+	{
+		match self {
+			Offset::Start(length) => start + length.resolve(total) as i32,
+			Offset::End(length) => start + total as i32 - length.resolve(total) as i32 - size as i32,
+			Offset::Center => start + (total as i32 - size as i32) / 2,
+		}
+	}
+
+	/// position to configure for window in frame with (before, after) extents,
+	/// window manager places the frame at the position, except for static
+	/// gravity, which places the client window itself (ICCCM 4.1.5)
+	fn resolve_framed(&self, start: i32, total: u32, size: u32, (before, after): (u32, u32),
+		static_gravity: bool) -> i32
+	{
+		let position = self.resolve(start, total, size + before + after);
+		if static_gravity {
+			position + before as i32
+		} else {
+			position
+		}
+	}
+}
+
+#[derive(Debug)]
 struct WindowGeometry {
-	size: Option<(u32, u32)>,
-	offset: Option<(bool, i32, bool, i32)>,
+	/// width and height, None to keep the window's
+	size: Option<(Option<Length>, Option<Length>)>,
+	/// x and y
+	offset: Option<(Offset, Offset)>,
+}
+
+impl WindowGeometry {
+	/// pixel sizes and offsets multiplied by factor, percentages kept
+	fn scale(self, factor: f32) -> Self
+	{
+		let length = |length: Length| match length {
+			Length::Pixel(value) => Length::Pixel((value as f32 * factor).round() as u32),
+			percent => percent,
+		};
+		WindowGeometry {
+			size: self.size.map(|(w, h)| (w.map(length), h.map(length))),
+			offset: self.offset.map(|(x, y)| (x.map(length), y.map(length))),
+		}
+	}
 }
 
 impl WindowType {
@@ -76,41 +279,373 @@ impl WindowType {
 	}
 }
 
+/// short names of _NET_WM_STATE atoms
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum WindowState {
+	Above,
+	Below,
+	Sticky,
+	SkipTaskbar,
+	SkipPager,
+	Fullscreen,
+	MaximizedVert,
+	MaximizedHorz,
+	Hidden,
+	Shaded,
+	Modal,
+	DemandsAttention,
+}
+
+impl WindowState {
+	fn atom_name(&self) -> &'static str
+	{
+		match self {
+			WindowState::Above => "_NET_WM_STATE_ABOVE",
+			WindowState::Below => "_NET_WM_STATE_BELOW",
+			WindowState::Sticky => "_NET_WM_STATE_STICKY",
+			WindowState::SkipTaskbar => "_NET_WM_STATE_SKIP_TASKBAR",
+			WindowState::SkipPager => "_NET_WM_STATE_SKIP_PAGER",
+			WindowState::Fullscreen => "_NET_WM_STATE_FULLSCREEN",
+			WindowState::MaximizedVert => "_NET_WM_STATE_MAXIMIZED_VERT",
+			WindowState::MaximizedHorz => "_NET_WM_STATE_MAXIMIZED_HORZ",
+			WindowState::Hidden => "_NET_WM_STATE_HIDDEN",
+			WindowState::Shaded => "_NET_WM_STATE_SHADED",
+			WindowState::Modal => "_NET_WM_STATE_MODAL",
+			WindowState::DemandsAttention => "_NET_WM_STATE_DEMANDS_ATTENTION",
+		}
+	}
+}
+
+/// action in the first data of _NET_WM_STATE message
+const _NET_WM_STATE_REMOVE: u32 = 0;
+const _NET_WM_STATE_ADD: u32 = 1;
+const _NET_WM_STATE_TOGGLE: u32 = 2;
+
+/// root window events to match window on
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum Trigger {
+	Reparent,
+	Map,
+	Both,
+}
+
+impl Trigger {
+	#[inline]
+	fn reparent(&self) -> bool
+	{
+		*self != Trigger::Map
+	}
+
+	#[inline]
+	fn map(&self) -> bool
+	{
+		*self != Trigger::Reparent
+	}
+}
+
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 struct Cli {
-	#[clap(short, long, help = "window match property, <class|name>=<property value>")]
+	#[clap(short, long, value_parser = parse_match_property, help = "window match property, <class|class_any|instance|name|name_startswith|name_endswith|role|exe>=<property value>, class and class_any match either instance or class of WM_CLASS, instance matches the first one only, exe is full path or basename of the executable of window's pid, linux only")]
 	property: Option<WindowMatchProperty>,
-	#[clap(short, long, help = "icon file")]
-	icon: Option<PathBuf>,
-	#[clap(short, long, value_enum)]
+	#[clap(short, long, help = "icon file, \"-\" for stdin, svg, ico, xpm and xbm supported, repeat to pack pre-rendered sizes from multiple files")]
+	icon: Vec<PathBuf>,
+	#[clap(long, conflicts_with_all = ["icon", "icon_1x", "icon_name", "auto_icon", "icon_raw"], help = "do not set icon, even if the config profile specifies one")]
+	no_icon: bool,
+	#[clap(long, conflicts_with_all = ["icon", "icon_1x", "icon_name", "auto_icon", "icon_raw"], help = "delete _NET_WM_ICON and icon pixmap of WM_HINTS, so window manager falls back to the theme icon")]
+	remove_icon: bool,
+	#[clap(long, requires = "remove_icon", help = "delete icon again when program sets it in the wait seconds")]
+	keep_icon_removed: bool,
+	#[clap(long, help = "set icon again when program changes it, in the wait seconds or --enforce-for seconds after applied")]
+	enforce: bool,
+	#[clap(long, requires = "enforce", default_value = "10", value_parser = parse_wait, help = "seconds to enforce icon after applied, fraction allowed")]
+	enforce_for: Duration,
+	#[clap(long, help = "also set icon pixmap and mask of WM_HINTS for old window managers, the pixmaps are freed when replaced by xicon, but kept until server reset after window closed")]
+	legacy_icon: bool,
+	#[clap(long, help = "set icon mask of WM_HINTS from alpha channel of this image, overrides the mask of --legacy-icon")]
+	icon_mask: Option<PathBuf>,
+	#[clap(long, help = "read back icon after set, and warn if the size mismatch")]
+	verify_icon: bool,
+	#[clap(long, conflicts_with = "icon", help = "icon name to resolve from icon theme")]
+	icon_name: Option<String>,
+	#[clap(long, help = "icon theme to resolve icon name, default to the current gtk/kde theme")]
+	icon_theme: Option<String>,
+	#[clap(long, help = "use icon of the command's desktop entry when no icon specified")]
+	auto_icon: bool,
+	#[clap(long = "icon-1x", requires = "icon_2x", conflicts_with = "icon", help = "icon file for standard dpi, packed with the 2x icon")]
+	icon_1x: Option<PathBuf>,
+	#[clap(long = "icon-2x", requires = "icon_1x", help = "icon file for hidpi, scaled to double size of the 1x icon if not")]
+	icon_2x: Option<PathBuf>,
+	#[clap(long, conflicts_with_all = ["icon", "icon_1x", "icon_name"], help = "raw icon file, \"-\" for stdin, of little-endian u32 width and height followed by width*height BGRA pixels, set as is, blocks of multiple sizes can be concatenated")]
+	icon_raw: Option<PathBuf>,
+	#[clap(long, value_delimiter = ',', help = "icon sizes to generate, sizes larger than the icon file are skipped [default: 16,24,32,48,64,128,256]")]
+	icon_sizes: Vec<u32>,
+	#[clap(long, conflicts_with = "icon_sizes", help = "scale icon into a single <ICON_SIZE>x<ICON_SIZE> square, non-square icon is padded with transparent pixels")]
+	icon_size: Option<u32>,
+	#[clap(long, value_parser = parse_icon_scale, help = "scale raster icon before generating sizes, format: <max> to downscale only, <max>! to upscale too, or <width>{xX}<height> for exact size")]
+	icon_scale: Option<IconScale>,
+	#[clap(long, conflicts_with = "icon_scale", value_parser = clap::value_parser!(u32).range(1..), help = "scale down raster icon to fit in <ICON_MAX_SIZE>x<ICON_MAX_SIZE>, same as --icon-scale <ICON_MAX_SIZE>")]
+	icon_max_size: Option<u32>,
+	#[clap(long, help = "skip ico frames, icon files and generated sizes smaller than <MIN_ICON_SIZE>x<MIN_ICON_SIZE>")]
+	min_icon_size: Option<u32>,
+	#[clap(long, help = "skip ico frames, icon files and generated sizes larger than <MAX_ICON_SIZE>x<MAX_ICON_SIZE>")]
+	max_icon_size: Option<u32>,
+	#[clap(long, value_parser = parse_class, help = "replace WM_CLASS with <instance>[,<class>], class is the same as instance if omitted")]
+	class: Option<(String, String)>,
+	#[clap(long, help = "set WM_WINDOW_ROLE for session managers")]
+	role: Option<String>,
+	#[clap(long, help = "set window title to this")]
+	title: Option<String>,
+	#[clap(long, requires = "title", help = "set title again when program changes it in the wait seconds")]
+	lock_title: bool,
+	#[clap(long, help = "shrink and move window to fit in _NET_WORKAREA of the current desktop, before other size options")]
+	resize_to_workarea: bool,
+	#[clap(short, long, value_enum, help = "min iconifies window, min-legacy adds hidden state instead as old versions did")]
 	size: Option<WindowSize>,
 	#[clap(short, long, help = "always on top")]
 	above: bool,
+	#[clap(long, conflicts_with = "above", help = "always below other windows, for desktop widgets")]
+	below: bool,
+	#[clap(long, help = "demands attention, by both the state and urgency hint of WM_HINTS, other hints kept")]
+	urgent: bool,
+	#[clap(long, help = "modal state for dialogs, kept above its transient parent, or all windows by some window managers when no parent")]
+	modal: bool,
+	#[clap(long, value_parser = parse_window_id, help = "set WM_TRANSIENT_FOR to this window, so window manager stacks and groups window as its dialog")]
+	transient_for: Option<Window>,
+	#[clap(long, value_delimiter = ',', help = "pin window to these kde activity ids, empty for all activities")]
+	kde_activities: Option<Vec<String>>,
+	#[clap(long, conflicts_with = "kde_activities", help = "show window on all kde activities")]
+	kde_all_activities: bool,
+	#[clap(long, help = "show window on all desktops")]
+	sticky: bool,
+	#[clap(long, value_parser = parse_desktop, help = "move window to this desktop, 0 based index or \"current\", ignored with --sticky")]
+	desktop: Option<Desktop>,
+	#[clap(long, conflicts_with = "desktop", help = "move window to desktop with this name in _NET_DESKTOP_NAMES, \"*\" suffix for prefix match, ignored with --sticky")]
+	desktop_name: Option<String>,
+	#[clap(long, value_enum, value_delimiter = ',', help = "add states, comma separated or repeated")]
+	state: Vec<WindowState>,
+	#[clap(long, value_enum, value_delimiter = ',', help = "remove states, for undoing states set by program")]
+	remove_state: Vec<WindowState>,
+	#[clap(long, value_enum, value_delimiter = ',', help = "toggle states")]
+	toggle_state: Vec<WindowState>,
 	#[clap(short = 'd', long, help = "no decoration")]
 	no_decoration: bool,
-	#[clap(short = 't', long = "type")]
-	win_type: Option<WindowType>,
-	#[clap(short, long, help = "format: [<width>{xX}<height>][{+-}<xoffset>{+-}<yoffset>]", allow_hyphen_values = true)]
+	#[clap(short = 't', long = "type", help = "repeat to set multiple types in order of preference, window manager uses the first one it understands")]
+	win_type: Vec<WindowType>,
+	#[clap(short, long, help = "format: [[<width>[%]]{xX}[<height>[%]]][{+-}<xoffset>[%]{+-}<yoffset>[%]], % for percentage of screen, c for offset to center, width or height omitted to keep it", allow_hyphen_values = true)]
 	geometry: Option<String>,
+	#[clap(long, requires = "geometry", value_parser = parse_geometry_scale, help = "multiply pixel values of geometry by this factor, for geometry in logical pixels on hidpi screen")]
+	geometry_scale: Option<f32>,
+	#[clap(long, help = "x core border width in pixels, not the decoration drawn by window manager")]
+	border_width: Option<u32>,
+	#[clap(long, help = "ignore _NET_FRAME_EXTENTS of the frame with decoration when positioning window")]
+	no_frame_adjust: bool,
+	#[clap(long, help = "geometry is relative to _NET_WORKAREA of window's desktop, so panels are not covered, within the monitor of --monitor")]
+	workarea: bool,
+	#[clap(long, help = "wait for window mapped and its size settled before applying geometry, for right or bottom offset without size")]
+	geometry_after_map: bool,
+	#[clap(long, help = "request geometry by _NET_MOVERESIZE_WINDOW, so window manager applies its gravity and strut policy")]
+	use_moveresize_window: bool,
+	#[clap(long, help = "center window on screen, same as +c+c offset of geometry, overrides offset of geometry")]
+	center: bool,
+	#[clap(long, value_parser = parse_size, help = "min window size in WM_NORMAL_HINTS, format: <width>{xX}<height>")]
+	min_size: Option<(u32, u32)>,
+	#[clap(long, value_parser = parse_size, help = "max window size in WM_NORMAL_HINTS, same min and max size make window not resizable")]
+	max_size: Option<(u32, u32)>,
+	#[clap(long, help = "monitor index, output name or \"primary\", geometry is relative to it instead of the whole screen")]
+	monitor: Option<String>,
+	#[clap(long, value_parser = parse_opacity, help = "window opacity by _NET_WM_WINDOW_OPACITY, from 0.0 to 1.0, also set to frame of window manager, 1.0 to delete it")]
+	opacity: Option<f64>,
+	#[clap(long, requires = "opacity", help = "set opacity to all descendant windows too")]
+	recursive: bool,
 	#[clap(short = 'k', long, help = "hide window in taskbar")]
 	no_taskbar_icon: bool,
-	#[clap(short, long, default_value = "10", help = "max seconds to wait for program to complete startup")]
-	wait: u64,
-	#[clap(short, long, help = "x11 program to run")]
-	command: String,
+	#[clap(long, help = "hide window in pagers and workspace overviews")]
+	skip_pager: bool,
+	#[clap(long, help = "hide window in both taskbar and pagers, same as --no-taskbar-icon --skip-pager")]
+	hide_everywhere: bool,
+	#[clap(long, visible_alias = "shaded", help = "shade (roll up) window")]
+	shade: bool,
+	#[clap(short, long, default_value = "10", value_parser = parse_wait, help = "max seconds to wait for program to complete startup, fraction allowed")]
+	wait: Duration,
+	#[clap(long, value_parser = parse_wait, help = "seconds to wait after window matched before applying, for programs changing properties once at startup, fraction allowed, not longer than --wait")]
+	delay: Option<Duration>,
+	#[clap(long, help = "also match windows of the descendant processes, linux only")]
+	match_pid_children: bool,
+	#[clap(long, help = "only match reparented window with an immediate child of this WM_CLASS, for programs embedding real content in a generic shell window")]
+	match_child_class: Option<String>,
+	#[clap(long, help = "also match windows whose pid is a descendant of the command by parent chain in /proc, linux only")]
+	match_descendants: bool,
+	#[clap(long, value_enum, default_value = "both", help = "events to match window on")]
+	trigger: Trigger,
+	#[clap(long, help = "also try to match on window creation, properties are set before it is mapped and reparented by window manager, others applied after mapped")]
+	apply_on_create: bool,
+	#[clap(long = "wait-for-pid-file", help = "match window with pid read from this file instead of the command's pid")]
+	wait_pid_file: Option<PathBuf>,
+	#[clap(long, help = "print property of matched window, can be repeated")]
+	get_property: Vec<String>,
+	#[clap(long, help = "append x11 protocol errors to this file instead of reporting them")]
+	x11_error_file: Option<PathBuf>,
+	#[clap(long, help = "config file")]
+	config: Option<PathBuf>,
+	#[clap(long, requires = "config", help = "profile name in config file, options from command line take precedence")]
+	profile: Option<String>,
+	#[clap(long, requires = "config", help = "validate config file and exit")]
+	config_check: bool,
+	#[clap(long = "print-supported-atoms", help = "print _NET_SUPPORTED atoms of the running window manager and exit")]
+	print_supported: bool,
+	#[clap(long = "list-windows", visible_alias = "net-client-list", help = "print windows in _NET_CLIENT_LIST of the window manager and exit")]
+	list_windows: bool,
+	#[clap(long, help = "print in json format, for --print-supported-atoms and --list-windows")]
+	json: bool,
+	#[clap(long, help = "working directory of the program")]
+	cwd: Option<PathBuf>,
+	#[clap(long, value_parser = parse_window_id, help = "apply to existing window with this id instead of running program")]
+	xid: Option<Window>,
+	#[clap(long, value_parser = parse_window_id, help = "stack window immediately above this sibling window")]
+	restack_above: Option<Window>,
+	#[clap(long, help = "raise window to top of the stack once, unlike --above, no state kept")]
+	raise: bool,
+	#[clap(long, conflicts_with = "raise", help = "lower window to bottom of the stack once, unlike --below, no state kept")]
+	lower: bool,
+	#[clap(long, help = "activate window to give it input focus, window manager may refuse it by its focus policy")]
+	focus: bool,
+	#[clap(long = "map", requires = "xid", help = "map window after apply other options")]
+	map_window: bool,
+	#[clap(long = "unmap", conflicts_with = "map_window", help = "unmap window after apply other options")]
+	unmap_window: bool,
+	#[clap(long, help = "only apply options when name of the running window manager starts with this, the program is started anyway")]
+	wm_name_prefix: Option<String>,
+	#[clap(long, help = "save id of matched window to this file, and apply to that window directly next time if it still exists")]
+	state_file: Option<PathBuf>,
+	#[clap(long, help = "keep running after applied, and apply again when window manager restarted, until the window destroyed")]
+	watch_wm_restart: bool,
+	#[clap(long, help = "run lua script with the matched window after other options applied, needs the lua feature")]
+	script: Option<PathBuf>,
+	#[clap(long, help = "print id of matched window to stdout in decimal and hex before applying, use with --foreground to capture it")]
+	print_id: bool,
+	#[clap(long, help = "match window normally, but only print the changes to apply")]
+	dry_run: bool,
+	#[clap(long, help = "change states in _NET_WM_STATE property directly instead of sending messages to window manager, only works before window is managed or without ewmh window manager")]
+	direct_property: bool,
+	#[clap(long, help = "x display to connect, default to $DISPLAY, also set as $DISPLAY of the program")]
+	display: Option<String>,
+	#[clap(long, help = "x screen number, default to the screen of display")]
+	screen: Option<usize>,
+	#[clap(short, long, help = "do not warn when running in wayland session")]
+	quiet: bool,
+	#[clap(short, long, help = "print what is done with elapsed time to stderr")]
+	verbose: bool,
+	#[clap(long, visible_alias = "no-fork", help = "run in foreground instead of daemonized, so logs and exit status reach the terminal")]
+	foreground: bool,
+	#[clap(short, long, required_unless_present_any = ["config_check", "print_supported", "list_windows", "xid"], help = "x11 program to run")]
+	command: Option<String>,
 	args: Vec<String>,
+	/// icon data read from stdin for "--icon -"
+	#[clap(skip)]
+	stdin_icon: Option<Vec<u8>>,
 }
 
 fn main() -> Result<()>
 {
-	let cli = Cli::parse();
-	if let Some(icon) = &cli.icon {
-		if !icon.exists() {
-			panic!("Icon file not exists: {:#?}", cli.icon)
+	START.get_or_init(SystemTime::now);
+	let mut cli = Cli::parse();
+	VERBOSE.store(cli.verbose, Ordering::Relaxed);
+	if cli.config_check {
+		return check_config(&cli);
+	}
+	if !cli.quiet && is_wayland_session() {
+		eprintln!("Warning: running in wayland session, only x programs by XWayland are affected, \
+			geometry and decoration control may not work for wayland native programs");
+	}
+	if cli.print_supported {
+		return print_supported_atoms(&cli);
+	}
+	if cli.list_windows {
+		return list_windows(&cli);
+	}
+	if let Some(path) = &cli.config {
+		let config = Config::load(path)?;
+		if let Some(name) = &cli.profile {
+			let profile = config.profile(name)?.clone();
+			profile.merge(&mut cli);
+		}
+	}
+	if cli.shade && matches!(cli.size, Some(WindowSize::Max | WindowSize::Fullscreen)) {
+		Cli::command()
+			.error(ErrorKind::ArgumentConflict, "--shade can not be used with --size max or fullscreen")
+			.exit();
+	}
+	if cli.modal && cli.win_type.iter().any(|t| matches!(t, WindowType::Dock | WindowType::Desktop)) {
+		Cli::command()
+			.error(ErrorKind::ArgumentConflict, "--modal can not be used with --type dock or desktop")
+			.exit();
+	}
+	if cli.delay.is_some_and(|delay| delay > cli.wait) {
+		Cli::command()
+			.error(ErrorKind::ValueValidation, "--delay can not be longer than --wait")
+			.exit();
+	}
+	if let (Some(min), Some(max)) = (cli.min_icon_size, cli.max_icon_size) {
+		if min > max {
+			Cli::command()
+				.error(ErrorKind::ValueValidation, "--min-icon-size can not be larger than --max-icon-size")
+				.exit();
+		}
+	}
+	if cli.hide_everywhere {
+		cli.no_taskbar_icon = true;
+		cli.skip_pager = true;
+	}
+	if let Some(icon_name) = &cli.icon_name {
+		let icon = icon_theme::lookup(icon_name, cli.icon_theme.as_deref())?;
+		cli.icon.push(icon);
+	}
+	if cli.auto_icon && cli.icon.is_empty() && cli.icon_1x.is_none() {
+		if let Some(command) = &cli.command {
+			match find_desktop_icon(command, &cli) {
+				Ok(icon) => cli.icon.push(icon),
+				Err(e) => eprintln!("Failed find icon from desktop entry: {e}"),
+			}
+		}
+	}
+	// stdin may not be kept after daemonized
+	let stdin_icons = cli.icon.iter()
+		.chain(&cli.icon_1x)
+		.chain(&cli.icon_2x)
+		.chain(&cli.icon_raw)
+		.chain(&cli.icon_mask)
+		.filter(|icon| is_stdin(icon))
+		.count();
+	if stdin_icons > 1 {
+		Cli::command()
+			.error(ErrorKind::ArgumentConflict, "stdin can only be used by one of the icon files")
+			.exit();
+	}
+	if stdin_icons == 1 {
+		let mut data = vec![];
+		io::stdin().read_to_end(&mut data)?;
+		cli.stdin_icon = Some(data);
+	}
+	for icon in cli.icon.iter().chain(&cli.icon_1x).chain(&cli.icon_2x).chain(&cli.icon_raw).chain(&cli.icon_mask) {
+		if !is_stdin(icon) && !icon.exists() {
+			panic!("Icon file not exists: {:#?}", icon)
 		}
 	}
+	// report invalid geometry before the program started
+	if let Some(geometry) = &cli.geometry {
+		parse_geometry(geometry)?;
+	}
 
+	if cli.foreground {
+		return match start(cli) {
+			Err(error) if error.is::<NoWindowMatched>() => {
+				eprintln!("{error}");
+				process::exit(2);
+			}
+			result => result,
+		};
+	}
 	match fork::daemon(false, true) {
 		Ok(Fork::Parent(_)) => Ok(()),
 		Ok(Fork::Child) => start(cli),
@@ -119,248 +654,2321 @@ fn main() -> Result<()>
 	// start(cli)
 }
 
-struct IconData {
-	data: Vec<u8>,
-	length: u32,
-}
-
 #[inline]
-fn start(cli: Cli) -> Result<()>
+fn is_wayland_session() -> bool
 {
-	let (conn, screen_num) = x11rb::connect(None)?;
-	let screen = &conn.setup().roots[screen_num];
-	let state_atom = get_atom(&conn, "_NET_WM_STATE")?;
+	env::var_os("WAYLAND_DISPLAY").is_some_and(|display| !display.is_empty())
+		|| env::var("XDG_SESSION_TYPE").is_ok_and(|session| session == "wayland")
+}
 
-	let mut aux = ChangeWindowAttributesAux::new();
-	aux.event_mask = Some(EventMask::SUBSTRUCTURE_NOTIFY);
-	conn.change_window_attributes(screen.root, &aux)?.check()?;
-	conn.flush()?;
-	let child = Command::new(cli.command).args(cli.args).spawn()?;
-	let pid = child.id();
-	let start = SystemTime::now();
-	loop {
-		let event = conn.wait_for_event()?;
-		if let Event::ReparentNotify(event) = event {
-			let win = event.window;
-			if match_window(&conn, win, pid, &cli.property)? {
-				if let Some(icon) = &cli.icon {
-					let icon = load_icon(icon)?;
-					set_icon(&conn, win, &icon)?;
-				}
-				if let Some(size) = &cli.size {
-					set_size(&conn, screen.root, win, size, state_atom)?;
-				}
-				if cli.above {
-					set_above(&conn, screen.root, win, state_atom)?;
-				}
-				if cli.no_decoration {
-					remove_decoration(&conn, win)?;
-				}
-				if let Some(win_type) = &cli.win_type {
-					set_type(&conn, win, win_type)?;
-				}
-				if let Some(geometry) = &cli.geometry {
-					set_geometry(&conn, screen, win, geometry)?;
-				}
-				if cli.no_taskbar_icon {
-					hide_taskbar_icon(&conn, screen.root, win, state_atom)?;
-				}
-				break;
-			}
-		}
-		let now = SystemTime::now();
-		let duration = now.duration_since(start)
-			.expect("Clock may have gone backwards");
-		if duration.as_secs() > cli.wait {
-			eprintln!("Failed to detect command windows in {} seconds, quit.", cli.wait);
-			break;
-		}
+/// exit with code 2 in foreground
+#[derive(Debug)]
+struct NoWindowMatched(Duration);
+
+impl fmt::Display for NoWindowMatched {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+	{
+		write!(f, "Failed to detect command windows in {} seconds, quit.", self.0.as_secs_f64())
 	}
-	Ok(())
 }
 
-fn match_window(conn: &RustConnection, current: Window, target_pid: u32,
-	match_property: &Option<WindowMatchProperty>) -> Result<bool>
-{
-	match match_property {
-		None => {
-			let pid_atom = get_atom(&conn, "_NET_WM_PID")?;
-			let pid_result = conn.get_property(
-				false,
-				current,
-				pid_atom,
-				AtomEnum::CARDINAL,
-				0, 1,
-			)?;
-			let pid_reply = pid_result.reply()?;
-			if pid_reply.length == 1 {
-				let pid = pid_reply.value32()
-					.expect("Invalid replay")
-					.next()
-					.expect("No pid exists in result");
-				Ok(pid == target_pid)
-			} else {
-				Ok(false)
-			}
-		}
-		Some(WindowMatchProperty::Class(value)) => {
-			let len = value.len();
-			let result = conn.get_property(
-				false,
-				current,
-				AtomEnum::WM_CLASS,
-				AtomEnum::STRING,
-				0,
-				len as u32)?;
-			let reply = result.reply()?;
-			let win_value = reply.value;
-			// class with two null-separated strings
-			let bytes = value.as_bytes();
-			for buf in win_value.split(|b| *b == 0) {
-				if buf.len() == len {
-					if compare_bytes(buf, bytes, len) {
-						return Ok(true);
-					}
-				}
-			}
-			Ok(false)
-		}
-		Some(WindowMatchProperty::Name(value)) => {
-			let len = value.len();
-			let result = conn.get_property(
-				false,
-				current,
-				AtomEnum::WM_NAME,
-				AtomEnum::STRING,
-				0,
-				len as u32)?;
-			let reply = result.reply()?;
-			let win_value = reply.value;
-			if win_value.len() == len {
-				Ok(compare_bytes(&win_value, value.as_bytes(), len))
-			} else {
-				Ok(false)
-			}
-		}
+impl std::error::Error for NoWindowMatched {}
+
+/// event the window detected by
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Detection {
+	Created,
+	Mapped,
+	Reparented,
+}
+
+impl fmt::Display for Detection {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result
+	{
+		let name = match self {
+			Detection::Created => "created",
+			Detection::Mapped => "mapped",
+			Detection::Reparented => "reparented",
+		};
+		f.write_str(name)
 	}
 }
 
 #[inline]
-fn compare_bytes(a: &[u8], b: &[u8], len: usize) -> bool
+fn is_verbose() -> bool
 {
-	for i in 0..len {
-		if a[i] != b[i] {
-			return false;
-		}
-	}
-	true
+	VERBOSE.load(Ordering::Relaxed)
 }
 
 #[inline]
-fn push_u32(data: &mut Vec<u8>, value: u32)
+fn elapsed() -> Duration
 {
-	let bytes = value.to_le_bytes();
-	for byte in bytes {
-		data.push(byte);
+	START.get()
+		.and_then(|start| SystemTime::now().duration_since(*start).ok())
+		.unwrap_or_default()
+}
+
+fn find_desktop_icon(command: &str, cli: &Cli) -> Result<PathBuf>
+{
+	let wm_class = match &cli.property {
+		Some(WindowMatchProperty::Class(class)) => Some(class.as_str()),
+		_ => None,
+	};
+	let icon = desktop_entry::find_icon(command, wm_class)
+		.ok_or_else(|| anyhow!("No desktop entry found for: {command}"))?;
+	let path = PathBuf::from(&icon);
+	if path.is_absolute() {
+		Ok(path)
+	} else {
+		icon_theme::lookup(&icon, cli.icon_theme.as_deref())
 	}
 }
 
-fn load_icon(icon: &PathBuf) -> Result<IconData>
+fn check_config(cli: &Cli) -> Result<()>
 {
-	let data = fs::read(icon)?;
-	let image = image::load_from_memory(&data)?;
-	let width = image.width();
-	let height = image.height();
-	let bytes = image.into_bytes();
-	let mut data = vec![];
-	push_u32(&mut data, width);
-	push_u32(&mut data, height);
-	let mut slice = bytes.as_slice();
-	while let [r, g, b, a, rest @ ..] = slice {
-		data.push(*b);
-		data.push(*g);
-		data.push(*r);
-		data.push(*a);
-		slice = rest;
-	}
-	let length = width * height + 2;
-	Ok(IconData { data, length })
+	let path = cli.config.as_ref().expect("No config file specified");
+	let config = Config::load(path)?;
+	let errors = match connect(cli) {
+		Ok((conn, _)) => config.check(Some(&conn)),
+		Err(e) => {
+			eprintln!("Failed connect to X server, skip atom check: {e}");
+			config.check(None)
+		}
+	};
+	if errors.is_empty() {
+		println!("Config OK");
+		Ok(())
+	} else {
+		for error in &errors {
+			eprintln!("{error}");
+		}
+		Err(anyhow!("{} error(s) found in config file: {}", errors.len(), path.display()))
+	}
 }
 
-#[inline]
-fn set_icon(conn: &RustConnection, win: Window, icon: &IconData) -> Result<()>
+/// atom names in _NET_SUPPORTED of root, sorted, one per line or a json array
+fn print_supported_atoms(cli: &Cli) -> Result<()>
 {
-	let set_icon_atom = get_atom(&conn, "_NET_WM_ICON")?;
-	conn.change_property(
-		PropMode::REPLACE,
-		win,
-		set_icon_atom,
-		AtomEnum::CARDINAL,
-		32,
-		icon.length,
-		&icon.data,
-	)?.check()?;
+	let (conn, screen_num) = connect(cli)?;
+	let root = conn.setup().roots[screen_num].root;
+	let supported_atom = get_atom(&conn, "_NET_SUPPORTED")?;
+	let reply = conn.get_property(false, root, supported_atom, AtomEnum::ATOM, 0, u32::MAX)?
+		.reply()?;
+	let mut names = vec![];
+	if let Some(atoms) = reply.value32() {
+		for atom in atoms {
+			names.push(get_atom_name(&conn, atom)?);
+		}
+	}
+	names.sort();
+	if cli.json {
+		println!("{}", json_array(&names));
+	} else {
+		for name in &names {
+			println!("{name}");
+		}
+	}
 	Ok(())
 }
 
-#[inline]
-fn send_message(conn: &RustConnection, root: Window, win: Window,
-	msg_type: Atom, data: [u32; 5]) -> Result<()>
-{
-	let event = ClientMessageEvent::new(
-		32, win, msg_type, data);
+struct WindowInfo {
+	id: Window,
+	pid: Option<u32>,
+	class: String,
+	name: String,
+	/// x, y, width and height relative to root
+	geometry: (i32, i32, u32, u32),
+}
 
-	conn.send_event(
-		true,
-		root,
-		EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
-		event,
-	)?.check()?;
-	Ok(())
+impl WindowInfo {
+	fn json(&self) -> String
+	{
+		let (x, y, width, height) = self.geometry;
+		let pid = self.pid.map_or_else(|| "null".to_owned(), |pid| pid.to_string());
+		format!("{{\"id\":{},\"pid\":{pid},\"class\":{},\"name\":{},\"x\":{x},\"y\":{y},\"width\":{width},\"height\":{height}}}",
+			self.id, json_string(&self.class), json_string(&self.name))
+	}
 }
 
-#[inline]
-fn set_size(conn: &RustConnection, root: Window, win: Window,
-	size: &WindowSize, state_atom: Atom) -> Result<()>
+/// windows in _NET_CLIENT_LIST as a table or json array
+fn list_windows(cli: &Cli) -> Result<()>
 {
-	match size {
-		WindowSize::Max => {
-			let vertical = get_atom(conn, "_NET_WM_STATE_MAXIMIZED_VERT")?;
-			let horizontal = get_atom(conn, "_NET_WM_STATE_MAXIMIZED_HORZ")?;
-			add_state(conn, root, win, state_atom, vertical, horizontal, 0, 0)
+	let (conn, screen_num) = connect(cli)?;
+	let root = conn.setup().roots[screen_num].root;
+	let list_atom = get_atom(&conn, "_NET_CLIENT_LIST")?;
+	let windows: Vec<Window> = conn.get_property(false, root, list_atom, AtomEnum::WINDOW, 0, u32::MAX)?
+		.reply()?
+		.value32()
+		.map(|windows| windows.collect())
+		.unwrap_or_default();
+	let mut infos = vec![];
+	for win in windows {
+		match window_info(&conn, root, win) {
+			Ok(info) => infos.push(info),
+			// destroyed after listed
+			Err(e) => verbose!("skip window {win:#x}: {e}"),
 		}
-		WindowSize::Min => {
-			let atom = get_atom(conn, "_NET_WM_STATE_HIDDEN")?;
-			add_state(conn, root, win, state_atom, atom, 0, 0, 0)
-		}
-		WindowSize::Fullscreen => {
-			let fs = get_atom(conn, "_NET_WM_STATE_FULLSCREEN")?;
-			add_state(conn, root, win, state_atom, fs, 0, 0, 0)
+	}
+	if cli.json {
+		let objects: Vec<String> = infos.iter().map(WindowInfo::json).collect();
+		println!("[{}]", objects.join(","));
+	} else {
+		println!("{:<10} {:>7} {:<20} {:<20} NAME", "ID", "PID", "GEOMETRY", "CLASS");
+		for info in &infos {
+			let (x, y, width, height) = info.geometry;
+			let pid = info.pid.map_or_else(|| "-".to_owned(), |pid| pid.to_string());
+			println!("{:<10} {pid:>7} {:<20} {:<20} {}", format!("{:#x}", info.id),
+				format!("{width}x{height}{x:+}{y:+}"), info.class, info.name);
 		}
 	}
+	Ok(())
 }
 
-#[inline]
-fn set_above(conn: &RustConnection, root: Window, win: Window, state_atom: Atom)
-	-> Result<()>
+fn window_info(conn: &RustConnection, root: Window, win: Window) -> Result<WindowInfo>
 {
-	let atom = get_atom(conn, "_NET_WM_STATE_ABOVE")?;
-	add_state(conn, root, win, state_atom, atom, 0, 0, 0)
+	let pid_atom = get_atom(conn, "_NET_WM_PID")?;
+	let pid = conn.get_property(false, win, pid_atom, AtomEnum::CARDINAL, 0, 1)?
+		.reply()?
+		.value32()
+		.and_then(|mut values| values.next());
+	let class = conn.get_property(false, win, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 1024)?
+		.reply()?
+		.value;
+	let class: Vec<String> = class.split(|b| *b == 0)
+		.filter(|value| !value.is_empty())
+		.map(|value| String::from_utf8_lossy(value).into_owned())
+		.collect();
+	let name = window_name(conn, win)?;
+	let geometry = conn.get_geometry(win)?.reply()?;
+	let position = conn.translate_coordinates(win, root, 0, 0)?.reply()?;
+	Ok(WindowInfo {
+		id: win,
+		pid,
+		class: class.join(","),
+		name,
+		geometry: (position.dst_x as i32, position.dst_y as i32, geometry.width as u32, geometry.height as u32),
+	})
 }
 
-#[inline]
-fn remove_decoration(conn: &RustConnection, win: Window) -> Result<()>
+fn json_array(values: &[String]) -> String
 {
-	const PROP_MOTIF_WM_HINTS_ELEMENTS: u32 = 5;
-	const MWM_HINTS_DECORATIONS: u32 = 1 << 1;
+	let values: Vec<String> = values.iter()
+		.map(|value| json_string(value))
+		.collect();
+	format!("[{}]", values.join(","))
+}
 
-	let decoration_property = get_atom(conn, "_MOTIF_WM_HINTS")?;
-	let mut data = vec![];
-	push_u32(&mut data, MWM_HINTS_DECORATIONS);
-	push_u32(&mut data, 0);
-	push_u32(&mut data, 0);
-	push_u32(&mut data, 0);
-	push_u32(&mut data, 0);
+fn json_string(value: &str) -> String
+{
+	let mut text = String::with_capacity(value.len() + 2);
+	text.push('"');
+	for ch in value.chars() {
+		match ch {
+			'"' => text.push_str("\\\""),
+			'\\' => text.push_str("\\\\"),
+			'\n' => text.push_str("\\n"),
+			'\r' => text.push_str("\\r"),
+			'\t' => text.push_str("\\t"),
+			ch if ch < ' ' => text.push_str(&format!("\\u{:04x}", ch as u32)),
+			ch => text.push(ch),
+		}
+	}
+	text.push('"');
+	text
+}
+
+const DEFAULT_ICON_SIZES: [u32; 7] = [16, 24, 32, 48, 64, 128, 256];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum IconScale {
+	/// fit into max x max, smaller images are upscaled only when set
+	Max(u32, bool),
+	Exact(u32, u32),
+}
+
+impl IconScale {
+	fn apply(&self, image: DynamicImage) -> DynamicImage
+	{
+		match *self {
+			IconScale::Max(max, upscale) => {
+				let size = image.width().max(image.height());
+				if size > max || (upscale && size < max) {
+					image.resize(max, max, FilterType::Lanczos3)
+				} else {
+					image
+				}
+			}
+			IconScale::Exact(width, height) => if image.width() == width && image.height() == height {
+				image
+			} else {
+				image.resize_exact(width, height, FilterType::Lanczos3)
+			}
+		}
+	}
+}
+
+#[derive(Default)]
+struct IconOptions<'a> {
+	sizes: &'a [u32],
+	square_size: Option<u32>,
+	scale: Option<IconScale>,
+	min_size: Option<u32>,
+	max_size: Option<u32>,
+	stdin: Option<&'a [u8]>,
+}
+
+impl IconOptions<'_> {
+	/// the larger side of image in --min-icon-size and --max-icon-size
+	#[inline]
+	fn size_allowed(&self, width: u32, height: u32) -> bool
+	{
+		let size = width.max(height);
+		self.min_size.is_none_or(|min| size >= min)
+			&& self.max_size.is_none_or(|max| size <= max)
+	}
+
+	/// sizes to generate from svg or raster icon, in the size range
+	fn generated_sizes(&self) -> Result<Vec<u32>>
+	{
+		let sizes: Vec<u32> = icon_sizes(self.sizes)
+			.into_iter()
+			.filter(|size| self.size_allowed(*size, *size))
+			.collect();
+		if sizes.is_empty() {
+			Err(anyhow!("No icon size in size range"))
+		} else {
+			Ok(sizes)
+		}
+	}
+}
+
+impl<'a> From<&'a Cli> for IconOptions<'a> {
+	fn from(cli: &'a Cli) -> Self
+	{
+		IconOptions {
+			sizes: &cli.icon_sizes,
+			square_size: cli.icon_size,
+			scale: cli.icon_scale
+				.or(cli.icon_max_size.map(|size| IconScale::Max(size, false))),
+			min_size: cli.min_icon_size,
+			max_size: cli.max_icon_size,
+			stdin: cli.stdin_icon.as_deref(),
+		}
+	}
+}
+
+struct IconData {
+	data: Vec<u8>,
+	length: u32,
+}
+
+impl IconData {
+	#[inline]
+	fn new() -> Self
+	{
+		IconData { data: vec![], length: 0 }
+	}
+
+	/// size of the first block
+	#[inline]
+	fn first_size(&self) -> (u32, u32)
+	{
+		let value = |i: usize| u32::from_le_bytes(self.data[i..i + 4].try_into().unwrap());
+		(value(0), value(4))
+	}
+
+	/// width, height and BGRA bytes of every block
+	fn blocks(&self) -> Vec<(u32, u32, &[u8])>
+	{
+		let mut blocks = vec![];
+		let mut data = self.data.as_slice();
+		while data.len() >= 8 {
+			let width = u32::from_le_bytes(data[0..4].try_into().unwrap());
+			let height = u32::from_le_bytes(data[4..8].try_into().unwrap());
+			let end = 8 + (width * height * 4) as usize;
+			let Some(pixels) = data.get(8..end) else {
+				break;
+			};
+			blocks.push((width, height, pixels));
+			data = &data[end..];
+		}
+		blocks
+	}
+
+	/// append one width/height/ARGB block
+	fn push_image(&mut self, image: DynamicImage) -> Result<()>
+	{
+		// make sure 4 bytes per pixel
+		let image = image.into_rgba8();
+		let width = image.width();
+		let height = image.height();
+		let bytes = image.into_raw();
+		if bytes.len() != (width * height * 4) as usize {
+			return Err(anyhow!("Invalid icon data, expect {} bytes for {width}x{height}, got {}",
+				width * height * 4, bytes.len()));
+		}
+		push_u32(&mut self.data, width);
+		push_u32(&mut self.data, height);
+		let mut slice = bytes.as_slice();
+		while let [r, g, b, a, rest @ ..] = slice {
+			self.data.push(*b);
+			self.data.push(*g);
+			self.data.push(*r);
+			self.data.push(*a);
+			slice = rest;
+		}
+		self.length += width * height + 2;
+		Ok(())
+	}
+}
+
+fn start(cli: Cli) -> Result<()>
+{
+	let error_file = cli.x11_error_file.clone();
+	let result = run(cli);
+	if let (Err(error), Some(error_file)) = (&result, &error_file) {
+		if let Some(ReplyError::X11Error(x11_error)) = error.downcast_ref::<ReplyError>() {
+			log_x11_error(error_file, x11_error)?;
+			return Err(anyhow!("X11 error logged to: {}", error_file.display()));
+		}
+	}
+	result
+}
+
+fn log_x11_error(error_file: &Path, error: &X11Error) -> Result<()>
+{
+	let mut file = OpenOptions::new()
+		.create(true)
+		.append(true)
+		.open(error_file)?;
+	writeln!(file, "{:?}: major opcode {}, minor opcode {}, bad value {:#x}, sequence {}",
+		error.error_kind, error.major_opcode, error.minor_opcode, error.bad_value, error.sequence)?;
+	Ok(())
+}
+
+/// connect to --display, with screen number of --screen if specified
+fn connect(cli: &Cli) -> Result<(RustConnection, usize)>
+{
+	let (conn, screen_num) = x11rb::connect(cli.display.as_deref())?;
+	let Some(screen_num) = cli.screen else {
+		return Ok((conn, screen_num));
+	};
+	let count = conn.setup().roots.len();
+	if screen_num >= count {
+		return Err(anyhow!("Screen {screen_num} not exists, display has {count} screen(s)"));
+	}
+	Ok((conn, screen_num))
+}
+
+#[inline]
+fn run(cli: Cli) -> Result<()>
+{
+	let (conn, screen_num) = connect(&cli)?;
+	let screen = &conn.setup().roots[screen_num];
+	let state_atom = get_atom(&conn, "_NET_WM_STATE")?;
+	verbose!("connected to X server, screen {screen_num}");
+
+	if let Some(prefix) = &cli.wm_name_prefix {
+		let name = wm_name(&conn, screen.root)?;
+		verbose!("window manager: {}", name.as_deref().unwrap_or("unknown"));
+		if !name.is_some_and(|name| name.starts_with(prefix)) {
+			if cli.xid.is_none() {
+				spawn_command(&cli)?;
+			}
+			return Ok(());
+		}
+	}
+
+	if let Some(win) = cli.xid {
+		verbose!("apply to window {win:#x}");
+		on_matched(win, &cli);
+		apply(&conn, screen, win, &cli, state_atom)?;
+		return watch_wm_restart(&conn, screen, win, &cli, state_atom);
+	}
+	if let Some(state_file) = &cli.state_file {
+		if let Some(win) = read_state_file(&conn, state_file)? {
+			verbose!("apply to window {win:#x} from {}", state_file.display());
+			on_matched(win, &cli);
+			apply(&conn, screen, win, &cli, state_atom)?;
+			return watch_wm_restart(&conn, screen, win, &cli, state_atom);
+		}
+	}
+
+	let mut aux = ChangeWindowAttributesAux::new();
+	aux.event_mask = Some(EventMask::SUBSTRUCTURE_NOTIFY);
+	conn.change_window_attributes(screen.root, &aux)?.check()?;
+	conn.flush()?;
+	let child = spawn_command(&cli)?;
+	let start = SystemTime::now();
+	verbose!("command started with pid {}", child.id());
+	let pid = if let Some(pid_file) = &cli.wait_pid_file {
+		let pid = wait_pid_file(pid_file, start, cli.wait)?;
+		verbose!("pid {pid} read from {}", pid_file.display());
+		pid
+	} else {
+		child.id()
+	};
+	// matched on creation with properties set, to apply the others when mapped
+	let mut created: Option<(Window, Option<IconData>)> = None;
+	loop {
+		let event = conn.wait_for_event()?;
+		if let (Event::Error(error), Some(error_file)) = (&event, &cli.x11_error_file) {
+			log_x11_error(error_file, error)?;
+		}
+		if let Some((win, icon)) = created.take() {
+			match event {
+				Event::MapNotify(event) if event.window == win => {
+					verbose!("window {win:#x} mapped");
+					apply_managed(&conn, screen, win, &cli, state_atom, icon.as_ref())?;
+					return finish_matched(&conn, screen, win, &cli, state_atom);
+				}
+				Event::DestroyNotify(event) if event.window == win => {
+					verbose!("window {win:#x} destroyed before mapped");
+				}
+				_ => created = Some((win, icon)),
+			}
+		} else if let Some((win, detection)) = detect(&event, &cli) {
+			if is_verbose() {
+				let description = describe_window(&conn, win)
+					.unwrap_or_else(|e| e.to_string());
+				verbose!("window {win:#x} {detection}, {description}");
+			}
+			let pids = if cli.match_pid_children {
+				descendant_pids(pid)
+			} else {
+				vec![pid]
+			};
+			let matched = match_window(&conn, win, &pids, cli.match_descendants, &cli.property)
+				.and_then(|matched| match &cli.match_child_class {
+					Some(class) if matched => Ok(detection == Detection::Reparented
+						&& child_class_matches(&conn, win, class)?),
+					_ => Ok(matched),
+				});
+			let matched = match matched {
+				Ok(matched) => matched,
+				// window may be destroyed already, like popups and tooltips
+				Err(error) if error.is::<ReplyError>() => {
+					verbose!("window {win:#x} skipped: {error}");
+					false
+				}
+				Err(error) => return Err(error),
+			};
+			if !matched {
+				verbose!("window {win:#x} not matched");
+			} else if detection != Detection::Created || cli.dry_run {
+				verbose!("window {win:#x} matched");
+				on_matched(win, &cli);
+				apply(&conn, screen, win, &cli, state_atom)?;
+				return finish_matched(&conn, screen, win, &cli, state_atom);
+			} else {
+				verbose!("window {win:#x} matched, set properties until it is mapped");
+				on_matched(win, &cli);
+				match apply_unmanaged(&conn, screen, win, &cli) {
+					Ok((icon, false)) => created = Some((win, icon)),
+					Ok((icon, true)) => {
+						verbose!("window {win:#x} mapped already");
+						apply_managed(&conn, screen, win, &cli, state_atom, icon.as_ref())?;
+						return finish_matched(&conn, screen, win, &cli, state_atom);
+					}
+					// destroyed already
+					Err(error) if error.is::<ReplyError>() => verbose!("window {win:#x} skipped: {error}"),
+					Err(error) => return Err(error),
+				}
+			}
+		}
+		let duration = SystemTime::now().duration_since(start).unwrap_or_default();
+		if duration > cli.wait {
+			return Err(NoWindowMatched(cli.wait).into());
+		}
+	}
+}
+
+/// window and the event it detected by, for the enabled triggers
+fn detect(event: &Event, cli: &Cli) -> Option<(Window, Detection)>
+{
+	match event {
+		Event::ReparentNotify(event) if cli.trigger.reparent() => Some((event.window, Detection::Reparented)),
+		Event::MapNotify(event) if cli.trigger.map() => Some((event.window, Detection::Mapped)),
+		Event::CreateNotify(event) if cli.apply_on_create => Some((event.window, Detection::Created)),
+		_ => None,
+	}
+}
+
+/// window manager ignores requests for windows not managed yet, so only set
+/// properties of the created window, return the icon set and whether it is
+/// mapped already
+fn apply_unmanaged(conn: &RustConnection, screen: &Screen, win: Window, cli: &Cli)
+	-> Result<(Option<IconData>, bool)>
+{
+	// MapNotify is sent to the frame instead of root when reparented before mapped
+	let aux = ChangeWindowAttributesAux::new()
+		.event_mask(EventMask::STRUCTURE_NOTIFY);
+	conn.change_window_attributes(win, &aux)?.check()?;
+	let icon = apply_properties(conn, screen, win, cli)?;
+	let attributes = conn.get_window_attributes(win)?.reply()?;
+	Ok((icon, attributes.map_state != MapState::UNMAPPED))
+}
+
+/// save matched window to state file, and watch window manager restart
+#[inline]
+fn finish_matched(conn: &RustConnection, screen: &Screen, win: Window, cli: &Cli,
+	state_atom: Atom) -> Result<()>
+{
+	if let Some(state_file) = cli.state_file.as_ref().filter(|_| !cli.dry_run) {
+		fs::write(state_file, format!("{win:#x}\n"))?;
+	}
+	watch_wm_restart(conn, screen, win, cli, state_atom)
+}
+
+/// print id and wait for --delay before applying
+#[inline]
+fn on_matched(win: Window, cli: &Cli)
+{
+	if cli.print_id {
+		println!("{win} {win:#x}");
+		// may be read by script before xicon exits
+		let _ = io::stdout().flush();
+	}
+	if let Some(delay) = cli.delay {
+		verbose!("wait {}s before applying", delay.as_secs_f64());
+		thread::sleep(delay);
+	}
+}
+
+/// apply again when _NET_SUPPORTING_WM_CHECK of root changed, which is set
+/// by the new window manager
+fn watch_wm_restart(conn: &RustConnection, screen: &Screen, win: Window, cli: &Cli,
+	state_atom: Atom) -> Result<()>
+{
+	if !cli.watch_wm_restart || cli.dry_run {
+		return Ok(());
+	}
+	let check_atom = get_atom(conn, "_NET_SUPPORTING_WM_CHECK")?;
+	let aux = ChangeWindowAttributesAux::new()
+		.event_mask(EventMask::PROPERTY_CHANGE | EventMask::SUBSTRUCTURE_NOTIFY);
+	conn.change_window_attributes(screen.root, &aux)?.check()?;
+	let aux = ChangeWindowAttributesAux::new()
+		.event_mask(EventMask::STRUCTURE_NOTIFY);
+	conn.change_window_attributes(win, &aux)?.check()?;
+	loop {
+		match conn.wait_for_event()? {
+			Event::PropertyNotify(event) if event.window == screen.root
+				&& event.atom == check_atom
+				&& event.state == Property::NEW_VALUE => {
+				verbose!("window manager restarted, apply again");
+				// give window manager time to manage existing windows
+				thread::sleep(Duration::from_secs(1));
+				apply(conn, screen, win, cli, state_atom)?;
+			}
+			Event::DestroyNotify(event) if event.window == win => {
+				verbose!("window {win:#x} destroyed");
+				return Ok(());
+			}
+			Event::Error(error) => if let Some(error_file) = &cli.x11_error_file {
+				log_x11_error(error_file, &error)?;
+			}
+			_ => {}
+		}
+	}
+}
+
+/// window saved in state file, None if not saved or not exists any more
+fn read_state_file(conn: &RustConnection, state_file: &Path) -> Result<Option<Window>>
+{
+	let Ok(text) = fs::read_to_string(state_file) else {
+		return Ok(None);
+	};
+	let win = parse_window_id(text.trim())
+		.map_err(|e| anyhow!("Invalid state file {}: {e}", state_file.display()))?;
+	match conn.get_window_attributes(win)?.reply() {
+		Ok(_) => Ok(Some(win)),
+		Err(ReplyError::X11Error(_)) => Ok(None),
+		Err(e) => Err(e.into()),
+	}
+}
+
+#[inline]
+fn spawn_command(cli: &Cli) -> Result<Child>
+{
+	let command = cli.command.as_ref().expect("No command specified");
+	let mut command = Command::new(command);
+	command.args(&cli.args);
+	if let Some(cwd) = &cli.cwd {
+		command.current_dir(cwd);
+	}
+	// program's windows are on the display matched
+	if let Some(display) = &cli.display {
+		command.env("DISPLAY", display);
+	}
+	Ok(command.spawn()?)
+}
+
+/// _NET_SUPPORTING_WM_CHECK of root is a window with the same property
+/// pointing to itself, a stale one is left by the exited window manager
+fn has_ewmh_wm(conn: &RustConnection, root: Window) -> Result<bool>
+{
+	let check_atom = get_atom(conn, "_NET_SUPPORTING_WM_CHECK")?;
+	let check_win = |win: Window| -> Result<Option<Window>> {
+		match conn.get_property(false, win, check_atom, AtomEnum::WINDOW, 0, 1)?.reply() {
+			Ok(reply) => Ok(reply.value32().and_then(|mut values| values.next())),
+			Err(ReplyError::X11Error(_)) => Ok(None),
+			Err(e) => Err(e.into()),
+		}
+	};
+	let Some(win) = check_win(root)? else {
+		return Ok(false);
+	};
+	Ok(check_win(win)? == Some(win))
+}
+
+/// _NET_WM_NAME of the _NET_SUPPORTING_WM_CHECK window
+fn wm_name(conn: &RustConnection, root: Window) -> Result<Option<String>>
+{
+	let check_atom = get_atom(conn, "_NET_SUPPORTING_WM_CHECK")?;
+	let check_win = conn.get_property(false, root, check_atom, AtomEnum::WINDOW, 0, 1)?
+		.reply()?
+		.value32()
+		.and_then(|mut values| values.next());
+	let Some(check_win) = check_win else {
+		return Ok(None);
+	};
+	let name_atom = get_atom(conn, "_NET_WM_NAME")?;
+	let utf8_atom = get_atom(conn, "UTF8_STRING")?;
+	let reply = conn.get_property(false, check_win, name_atom, utf8_atom, 0, 1024)?
+		.reply()?;
+	if reply.type_ == x11rb::NONE {
+		return Ok(None);
+	}
+	Ok(Some(String::from_utf8_lossy(&reply.value).into_owned()))
+}
+
+#[inline]
+fn load_cli_icon(cli: &Cli) -> Result<Option<IconData>>
+{
+	if cli.no_icon {
+		Ok(None)
+	} else if !cli.icon.is_empty() {
+		Ok(Some(load_icons(&cli.icon, &IconOptions::from(cli))?))
+	} else if let (Some(icon_1x), Some(icon_2x)) = (&cli.icon_1x, &cli.icon_2x) {
+		Ok(Some(load_hidpi_icon(icon_1x, icon_2x, cli.stdin_icon.as_deref())?))
+	} else if let Some(icon_raw) = &cli.icon_raw {
+		let data = read_icon_file(icon_raw, cli.stdin_icon.as_deref())?;
+		Ok(Some(load_raw_icon(data.into_owned())?))
+	} else {
+		Ok(None)
+	}
+}
+
+fn apply(conn: &RustConnection, screen: &Screen, win: Window, cli: &Cli,
+	state_atom: Atom) -> Result<()>
+{
+	if cli.dry_run {
+		return print_changes(conn, screen, win, cli);
+	}
+	let icon = apply_properties(conn, screen, win, cli)?;
+	apply_managed(conn, screen, win, cli, state_atom, icon.as_ref())
+}
+
+/// options only set properties of the window, which are read by window
+/// manager when managing it, return the icon set
+fn apply_properties(conn: &RustConnection, screen: &Screen, win: Window, cli: &Cli)
+	-> Result<Option<IconData>>
+{
+	// set as early as possible, window manager may apply its class based
+	// rules with the original one before this
+	if let Some((instance, class)) = &cli.class {
+		set_class(conn, win, instance, class)?;
+		verbose!("class set to {instance},{class}");
+	}
+	if cli.remove_icon {
+		remove_icon(conn, win)?;
+		verbose!("icon removed");
+	}
+	let icon = load_cli_icon(cli)?;
+	if let Some(icon) = &icon {
+		set_icon(conn, win, icon)?;
+		verbose!("icon set with {} bytes", icon.data.len());
+		if cli.verify_icon {
+			let (width, height) = icon.first_size();
+			if !verify_icon(conn, win, width, height)? {
+				eprintln!("Icon verify failed, expect size: {width}x{height}");
+			}
+		}
+		if cli.legacy_icon {
+			set_legacy_icon(conn, screen, win, icon)?;
+			verbose!("legacy icon set in WM_HINTS");
+		}
+	}
+	if let Some(path) = &cli.icon_mask {
+		set_icon_mask(conn, screen.root, win, path, cli.stdin_icon.as_deref())?;
+		verbose!("icon mask set in WM_HINTS");
+	}
+	if let Some(role) = &cli.role {
+		set_role(conn, win, role)?;
+		verbose!("role set to {role}");
+	}
+	if let Some(title) = &cli.title {
+		set_title(conn, win, title)?;
+		verbose!("title set to {title}");
+	}
+	// before modal, which is relative to the transient parent
+	if let Some(parent) = cli.transient_for {
+		set_transient_for(conn, win, parent)?;
+		verbose!("transient for {parent:#x}");
+	}
+	if let Some(activities) = kde_activities(cli) {
+		set_kde_activities(conn, win, &activities)?;
+		verbose!("kde activities set to {activities}");
+	}
+	if cli.no_decoration {
+		remove_decoration(conn, win)?;
+		verbose!("decoration removed");
+	}
+	if !cli.win_type.is_empty() {
+		set_type(conn, win, &cli.win_type)?;
+		verbose!("type set to {}", type_names(&cli.win_type));
+	}
+	if let Some(width) = cli.border_width {
+		set_border_width(conn, win, width)?;
+		verbose!("border width set to {width}");
+	}
+	if cli.min_size.is_some() || cli.max_size.is_some() {
+		set_size_hints(conn, win, cli.min_size, cli.max_size)?;
+		verbose!("size hints set, min: {:?}, max: {:?}", cli.min_size, cli.max_size);
+	}
+	if let Some(opacity) = cli.opacity {
+		set_opacity(conn, win, opacity, cli.recursive)?;
+		verbose!("opacity set to {opacity}");
+	}
+	Ok(icon)
+}
+
+/// options requesting window manager, which ignores windows not managed yet
+fn apply_managed(conn: &RustConnection, screen: &Screen, win: Window, cli: &Cli,
+	state_atom: Atom, icon: Option<&IconData>) -> Result<()>
+{
+	// nobody handles the messages without ewmh window manager
+	let ewmh = has_ewmh_wm(conn, screen.root)?;
+	if !ewmh {
+		verbose!("no ewmh window manager, change states directly and set geometry in WM_NORMAL_HINTS");
+	}
+	let state = NetWmState {
+		atom: state_atom,
+		direct: cli.direct_property || !ewmh,
+	};
+	if cli.resize_to_workarea {
+		resize_to_workarea(conn, screen.root, win)?;
+	}
+	if let Some(size) = &cli.size {
+		if ewmh {
+			set_size(conn, screen.root, win, size, state)?;
+			verbose!("size set to {size:?}");
+		} else if let WindowSize::Min | WindowSize::MinLegacy = size {
+			// WM_CHANGE_STATE is ICCCM, works without ewmh
+			iconify(conn, screen.root, win)?;
+			verbose!("iconify requested");
+		} else {
+			eprintln!("No ewmh window manager, configure window to fill the screen instead of size {size:?}");
+			fill_area(conn, &target_area(conn, screen, cli)?, win, size)?;
+		}
+	}
+	if cli.above {
+		set_above(conn, screen.root, win, state)?;
+		verbose!("above state added");
+	}
+	if cli.below {
+		set_below(conn, screen.root, win, state)?;
+		verbose!("below state added");
+	}
+	if cli.modal {
+		set_modal(conn, screen.root, win, state)?;
+		verbose!("modal state added");
+	}
+	if cli.urgent {
+		set_urgent(conn, screen.root, win, state)?;
+		verbose!("urgent set");
+	}
+	for (action, states) in [
+		(_NET_WM_STATE_ADD, &cli.state),
+		(_NET_WM_STATE_REMOVE, &cli.remove_state),
+		(_NET_WM_STATE_TOGGLE, &cli.toggle_state),
+	] {
+		if !states.is_empty() {
+			change_states(conn, screen.root, win, state, action, states)?;
+			verbose!("states changed with action {action}: {states:?}");
+		}
+	}
+	if cli.sticky {
+		set_sticky(conn, screen.root, win, state)?;
+		verbose!("sticky set");
+	} else if let Some(desktop) = cli.desktop {
+		let index = set_desktop(conn, screen.root, win, desktop)?;
+		verbose!("moved to desktop {index}");
+	} else if let Some(name) = &cli.desktop_name {
+		let index = desktop_by_name(conn, screen.root, name)?;
+		set_desktop(conn, screen.root, win, Desktop::Index(index))?;
+		verbose!("moved to desktop {index} named {name}");
+	}
+	if cli.geometry.is_some() || cli.center {
+		let area = geometry_area(conn, screen, win, cli)?;
+		if cli.geometry_after_map {
+			wait_size_settled(conn, win, cli.wait)?;
+		}
+		let extents = if cli.no_frame_adjust || !ewmh {
+			None
+		} else {
+			frame_extents(conn, screen.root, win, true)?
+		};
+		let aux = geometry_aux(conn, &area, win, cli.geometry.as_deref(), cli.geometry_scale, cli.center, extents)?;
+		let moveresize = cli.use_moveresize_window
+			&& is_supported(conn, screen.root, get_atom(conn, "_NET_MOVERESIZE_WINDOW")?)?;
+		if moveresize {
+			net_moveresize_window(conn, screen.root, win, window_gravity(conn, win)?, &aux)?;
+		} else {
+			if cli.use_moveresize_window {
+				verbose!("_NET_MOVERESIZE_WINDOW not supported by window manager, configure window directly");
+			}
+			set_geometry(conn, win, &aux, !ewmh)?;
+		}
+		verbose!("geometry applied in area {area:?}");
+	}
+	if cli.no_taskbar_icon {
+		hide_taskbar_icon(conn, screen.root, win, state)?;
+		verbose!("skip taskbar state added");
+	}
+	if cli.skip_pager {
+		hide_pager(conn, screen.root, win, state)?;
+		verbose!("skip pager state added");
+	}
+	if cli.shade {
+		set_shade(conn, screen.root, win, state)?;
+		verbose!("shaded state added");
+	}
+	if let Some(sibling) = cli.restack_above {
+		let aux = ConfigureWindowAux::new()
+			.sibling(sibling)
+			.stack_mode(StackMode::ABOVE);
+		conn.configure_window(win, &aux)?.check()?;
+		verbose!("restacked above {sibling:#x}");
+	}
+	if cli.raise || cli.lower {
+		let stack_mode = if cli.raise { StackMode::ABOVE } else { StackMode::BELOW };
+		let aux = ConfigureWindowAux::new().stack_mode(stack_mode);
+		conn.configure_window(win, &aux)?.check()?;
+		verbose!("window {}", if cli.raise { "raised" } else { "lowered" });
+	}
+	if cli.map_window {
+		conn.map_window(win)?.check()?;
+		verbose!("window mapped");
+	}
+	if cli.unmap_window {
+		conn.unmap_window(win)?.check()?;
+		verbose!("window unmapped");
+	}
+	for name in &cli.get_property {
+		print_property(conn, win, name)?;
+	}
+	if cli.focus {
+		focus_window(conn, screen.root, win)?;
+		verbose!("window focused");
+	}
+	if let Some(path) = &cli.script {
+		script::run_script(conn, screen, win, state, path)?;
+		verbose!("script {} finished", path.display());
+	}
+	verbose!("all options applied to window {win:#x}");
+	if cli.enforce {
+		watch_window(conn, win, cli, icon, cli.wait.max(elapsed() + cli.enforce_for))?;
+	} else if cli.keep_icon_removed || cli.lock_title {
+		watch_window(conn, win, cli, None, cli.wait)?;
+	}
+	Ok(())
+}
+
+/// revert property changes made by the program until the time since xicon
+/// started, icon set again if changed when specified
+fn watch_window(conn: &RustConnection, win: Window, cli: &Cli, icon: Option<&IconData>,
+	until: Duration) -> Result<()>
+{
+	let icon_atom = get_atom(conn, "_NET_WM_ICON")?;
+	let net_name_atom = get_atom(conn, "_NET_WM_NAME")?;
+	let aux = ChangeWindowAttributesAux::new()
+		.event_mask(EventMask::PROPERTY_CHANGE | EventMask::STRUCTURE_NOTIFY);
+	conn.change_window_attributes(win, &aux)?.check()?;
+	while elapsed() <= until {
+		let Some(event) = conn.poll_for_event()? else {
+			thread::sleep(Duration::from_millis(50));
+			continue;
+		};
+		match event {
+			Event::PropertyNotify(event) if event.window != win
+				|| event.state != Property::NEW_VALUE => {}
+			Event::PropertyNotify(event) if cli.keep_icon_removed && event.atom == icon_atom => {
+				verbose!("icon set by program, remove again");
+				remove_icon(conn, win)?;
+			}
+			Event::PropertyNotify(event) if event.atom == icon_atom => if let Some(icon) = icon {
+				// our own change is notified too
+				let value = conn.get_property(false, win, icon_atom, AtomEnum::CARDINAL, 0, u32::MAX)?
+					.reply()?
+					.value;
+				if value != icon.data {
+					verbose!("icon changed by program, set again");
+					set_icon(conn, win, icon)?;
+				}
+			}
+			Event::PropertyNotify(event) if cli.lock_title && (event.atom == net_name_atom
+				|| event.atom == u32::from(AtomEnum::WM_NAME)) => if let Some(title) = &cli.title {
+				// our own change is notified too
+				let value = conn.get_property(false, win, event.atom, AtomEnum::ANY, 0, u32::MAX)?
+					.reply()?
+					.value;
+				let expected = if event.atom == net_name_atom {
+					title.as_bytes().to_vec()
+				} else {
+					latin1(title)
+				};
+				if value != expected {
+					verbose!("title changed by program, set again");
+					set_title(conn, win, title)?;
+				}
+			}
+			Event::DestroyNotify(event) if event.window == win => break,
+			_ => {}
+		}
+	}
+	Ok(())
+}
+
+/// WM_CLASS with two null-terminated strings
+fn set_class(conn: &RustConnection, win: Window, instance: &str, class: &str) -> Result<()>
+{
+	let mut data = vec![];
+	data.extend(instance.as_bytes());
+	data.push(0);
+	data.extend(class.as_bytes());
+	data.push(0);
+	conn.change_property(
+		PropMode::REPLACE,
+		win,
+		AtomEnum::WM_CLASS,
+		AtomEnum::STRING,
+		8,
+		data.len() as u32,
+		&data,
+	)?.check()?;
+	Ok(())
+}
+
+/// WM_NAME in latin-1 and _NET_WM_NAME in utf-8
+fn set_title(conn: &RustConnection, win: Window, title: &str) -> Result<()>
+{
+	let name_atom = get_atom(conn, "_NET_WM_NAME")?;
+	let utf8_atom = get_atom(conn, "UTF8_STRING")?;
+	let data = latin1(title);
+	conn.change_property(
+		PropMode::REPLACE,
+		win,
+		AtomEnum::WM_NAME,
+		AtomEnum::STRING,
+		8,
+		data.len() as u32,
+		&data,
+	)?.check()?;
+	conn.change_property(
+		PropMode::REPLACE,
+		win,
+		name_atom,
+		utf8_atom,
+		8,
+		title.len() as u32,
+		title.as_bytes(),
+	)?.check()?;
+	Ok(())
+}
+
+/// characters not in latin-1 are replaced with '?'
+#[inline]
+fn latin1(text: &str) -> Vec<u8>
+{
+	text.chars()
+		.map(|ch| u8::try_from(ch).unwrap_or(b'?'))
+		.collect()
+}
+
+/// print what apply would change to stdout
+fn print_changes(conn: &RustConnection, screen: &Screen, win: Window, cli: &Cli) -> Result<()>
+{
+	println!("window: {win:#x}");
+	if let Some(icon) = load_cli_icon(cli)? {
+		let sizes: Vec<String> = icon.blocks().iter()
+			.map(|(width, height, _)| format!("{width}x{height}"))
+			.collect();
+		println!("_NET_WM_ICON: [{}], {} bytes", sizes.join(", "), icon.data.len());
+		if cli.legacy_icon {
+			println!("WM_HINTS: icon pixmap and mask");
+		}
+	}
+	if let Some(path) = &cli.icon_mask {
+		println!("WM_HINTS: icon mask from {}", path.display());
+	}
+	if cli.remove_icon {
+		println!("_NET_WM_ICON: delete{}", if cli.keep_icon_removed { ", keep removed" } else { "" });
+	}
+	if let Some((instance, class)) = &cli.class {
+		println!("WM_CLASS: {instance},{class}");
+	}
+	if let Some(role) = &cli.role {
+		println!("WM_WINDOW_ROLE: {role}");
+	}
+	if let Some(parent) = cli.transient_for {
+		println!("WM_TRANSIENT_FOR: {parent:#x}");
+	}
+	if let Some(title) = &cli.title {
+		println!("WM_NAME, _NET_WM_NAME: {title}{}", if cli.lock_title { ", locked" } else { "" });
+	}
+	if cli.resize_to_workarea {
+		println!("geometry: fit in _NET_WORKAREA");
+	}
+	if matches!(cli.size, Some(WindowSize::Min)) {
+		println!("WM_CHANGE_STATE: iconic");
+	}
+	let mut states = vec![];
+	if let Some(size) = &cli.size {
+		states.extend(size.atom_names());
+	}
+	if cli.above {
+		states.push("_NET_WM_STATE_ABOVE");
+	}
+	if cli.below {
+		states.push("_NET_WM_STATE_BELOW");
+	}
+	if cli.modal {
+		states.push("_NET_WM_STATE_MODAL");
+	}
+	if cli.urgent {
+		states.push("_NET_WM_STATE_DEMANDS_ATTENTION");
+		println!("WM_HINTS: urgency");
+	}
+	if cli.sticky {
+		states.push("_NET_WM_STATE_STICKY");
+		println!("_NET_WM_DESKTOP: {:#x}", ALL_DESKTOPS);
+	} else if let Some(desktop) = cli.desktop {
+		match desktop {
+			Desktop::Index(index) => println!("_NET_WM_DESKTOP: {index}"),
+			Desktop::Current => println!("_NET_WM_DESKTOP: current"),
+		}
+	} else if let Some(name) = &cli.desktop_name {
+		println!("_NET_WM_DESKTOP: {}", desktop_by_name(conn, screen.root, name)?);
+	}
+	if cli.no_taskbar_icon {
+		states.push("_NET_WM_STATE_SKIP_TASKBAR");
+	}
+	if cli.skip_pager {
+		states.push("_NET_WM_STATE_SKIP_PAGER");
+	}
+	if cli.shade {
+		states.push("_NET_WM_STATE_SHADED");
+	}
+	states.extend(cli.state.iter().map(WindowState::atom_name));
+	for (action, states) in [
+		("add", &states),
+		("remove", &cli.remove_state.iter().map(WindowState::atom_name).collect()),
+		("toggle", &cli.toggle_state.iter().map(WindowState::atom_name).collect()),
+	] {
+		if !states.is_empty() {
+			println!("_NET_WM_STATE: {action} [{}]", states.join(", "));
+		}
+	}
+	if let Some(activities) = kde_activities(cli) {
+		println!("_KDE_NET_WM_ACTIVITIES: {activities}");
+	}
+	if cli.no_decoration {
+		println!("_MOTIF_WM_HINTS: no decoration");
+	}
+	if !cli.win_type.is_empty() {
+		println!("_NET_WM_WINDOW_TYPE: {}", type_names(&cli.win_type));
+	}
+	if cli.geometry.is_some() || cli.center {
+		let area = geometry_area(conn, screen, win, cli)?;
+		let extents = if cli.no_frame_adjust {
+			None
+		} else {
+			frame_extents(conn, screen.root, win, false)?
+		};
+		let aux = geometry_aux(conn, &area, win, cli.geometry.as_deref(), cli.geometry_scale, cli.center, extents)?;
+		println!("geometry: x: {:?}, y: {:?}, width: {:?}, height: {:?}",
+			aux.x, aux.y, aux.width, aux.height);
+	}
+	if let Some(width) = cli.border_width {
+		println!("border width: {width}");
+	}
+	if cli.min_size.is_some() || cli.max_size.is_some() {
+		println!("WM_NORMAL_HINTS: min size: {:?}, max size: {:?}", cli.min_size, cli.max_size);
+	}
+	if let Some(opacity) = cli.opacity {
+		println!("_NET_WM_WINDOW_OPACITY: {opacity}{}", if cli.recursive { ", recursive" } else { "" });
+	}
+	if let Some(sibling) = cli.restack_above {
+		println!("stack: above {sibling:#x}");
+	}
+	if cli.raise {
+		println!("stack: raise");
+	}
+	if cli.lower {
+		println!("stack: lower");
+	}
+	if cli.focus {
+		println!("_NET_ACTIVE_WINDOW: {win:#x}");
+	}
+	if let Some(path) = &cli.script {
+		println!("script: {}", path.display());
+	}
+	if cli.map_window {
+		println!("map window");
+	}
+	if cli.unmap_window {
+		println!("unmap window");
+	}
+	Ok(())
+}
+
+/// seconds with optional fraction
+fn parse_wait(value: &str) -> Result<Duration, String>
+{
+	let seconds: f64 = value.parse()
+		.map_err(|_| format!("invalid seconds: {value}"))?;
+	Duration::try_from_secs_f64(seconds)
+		.map_err(|e| format!("invalid seconds: {value}, {e}"))
+}
+
+/// <instance>[,<class>]
+fn parse_class(value: &str) -> Result<(String, String), String>
+{
+	let (instance, class) = value.split_once(',').unwrap_or((value, value));
+	if instance.is_empty() || class.is_empty() || class.contains(',') {
+		return Err(format!("invalid class: {value}"));
+	}
+	Ok((instance.to_owned(), class.to_owned()))
+}
+
+fn parse_geometry_scale(value: &str) -> Result<f32, String>
+{
+	match value.parse::<f32>() {
+		Ok(factor) if factor > 0.0 && factor.is_finite() => Ok(factor),
+		_ => Err(format!("invalid geometry scale: {value}")),
+	}
+}
+
+fn parse_opacity(value: &str) -> Result<f64, String>
+{
+	let opacity: f64 = value.parse()
+		.map_err(|_| format!("invalid opacity: {value}"))?;
+	if (0.0..=1.0).contains(&opacity) {
+		Ok(opacity)
+	} else {
+		Err(format!("opacity out of range 0.0 to 1.0: {value}"))
+	}
+}
+
+#[inline]
+fn parse_match_property(value: &str) -> Result<WindowMatchProperty, String>
+{
+	WindowMatchProperty::try_from(value)
+}
+
+/// <width>{xX}<height>
+fn parse_size(value: &str) -> Result<(u32, u32), String>
+{
+	let re = Regex::new(r"^(\d+)[xX](\d+)$").unwrap();
+	let captures = re.captures(value)
+		.ok_or_else(|| format!("invalid size: {value}"))?;
+	let width = captures[1].parse().map_err(|_| format!("invalid width: {value}"))?;
+	let height = captures[2].parse().map_err(|_| format!("invalid height: {value}"))?;
+	Ok((width, height))
+}
+
+/// <max>, <max>! or <width>{xX}<height>
+fn parse_icon_scale(value: &str) -> Result<IconScale, String>
+{
+	let invalid = || format!("invalid icon scale: {value}");
+	if let Ok((width, height)) = parse_size(value) {
+		if width == 0 || height == 0 {
+			return Err(invalid());
+		}
+		return Ok(IconScale::Exact(width, height));
+	}
+	let (max, upscale) = match value.strip_suffix('!') {
+		Some(max) => (max, true),
+		None => (value, false),
+	};
+	match max.parse() {
+		Ok(0) | Err(_) => Err(invalid()),
+		Ok(max) => Ok(IconScale::Max(max, upscale)),
+	}
+}
+
+/// <index> or current
+fn parse_desktop(value: &str) -> Result<Desktop, String>
+{
+	if value == "current" {
+		return Ok(Desktop::Current);
+	}
+	value.parse()
+		.map(Desktop::Index)
+		.map_err(|_| format!("invalid desktop: {value}, expect index or \"current\""))
+}
+
+/// window id in decimal or hex with 0x prefix
+#[inline]
+fn parse_window_id(value: &str) -> Result<Window, String>
+{
+	let result = match value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")) {
+		Some(hex) => Window::from_str_radix(hex, 16),
+		None => value.parse(),
+	};
+	result.map_err(|_| format!("invalid window id: {value}"))
+}
+
+fn wait_pid_file(pid_file: &PathBuf, start: SystemTime, wait: Duration) -> Result<u32>
+{
+	loop {
+		// file may be partially written, retry until a valid pid is read
+		if let Ok(text) = fs::read_to_string(pid_file) {
+			if let Ok(pid) = text.trim().parse() {
+				return Ok(pid);
+			}
+		}
+		let duration = SystemTime::now().duration_since(start).unwrap_or_default();
+		if duration > wait {
+			return Err(anyhow!("Failed to read pid from {} in {} seconds", pid_file.display(), wait.as_secs_f64()));
+		}
+		thread::sleep(Duration::from_millis(100));
+	}
+}
+
+/// the pid and all its descendants from /proc/<pid>/task/<tid>/children
+fn descendant_pids(pid: u32) -> Vec<u32>
+{
+	let mut pids = vec![pid];
+	let mut i = 0;
+	while i < pids.len() {
+		let tasks = PathBuf::from(format!("/proc/{}/task", pids[i]));
+		i += 1;
+		let Ok(entries) = fs::read_dir(tasks) else {
+			continue;
+		};
+		for entry in entries.flatten() {
+			let Ok(text) = fs::read_to_string(entry.path().join("children")) else {
+				continue;
+			};
+			for child in text.split_whitespace().filter_map(|child| child.parse().ok()) {
+				if !pids.contains(&child) {
+					pids.push(child);
+				}
+			}
+		}
+	}
+	pids
+}
+
+/// parent pid from /proc/<pid>/stat
+fn parent_pid(pid: u32) -> Option<u32>
+{
+	let stat = fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+	parse_parent_pid(&stat)
+}
+
+/// "<pid> (<comm>) <state> <ppid> ...", comm may contain spaces and parentheses
+#[inline]
+fn parse_parent_pid(stat: &str) -> Option<u32>
+{
+	let (_, rest) = stat.rsplit_once(')')?;
+	rest.split_whitespace().nth(1)?.parse().ok()
+}
+
+fn is_descendant(mut pid: u32, ancestors: &[u32]) -> bool
+{
+	while pid > 1 {
+		let Some(parent) = parent_pid(pid) else {
+			return false;
+		};
+		if ancestors.contains(&parent) {
+			return true;
+		}
+		pid = parent;
+	}
+	false
+}
+
+fn match_window(conn: &RustConnection, current: Window, target_pids: &[u32],
+	descendants: bool, match_property: &Option<WindowMatchProperty>) -> Result<bool>
+{
+	match match_property {
+		None => {
+			let pid_atom = get_atom(conn, "_NET_WM_PID")?;
+			let pid_result = conn.get_property(
+				false,
+				current,
+				pid_atom,
+				AtomEnum::CARDINAL,
+				0, 1,
+			)?;
+			let pid_reply = pid_result.reply()?;
+			if pid_reply.length == 1 {
+				let pid = pid_reply.value32()
+					.expect("Invalid replay")
+					.next()
+					.expect("No pid exists in result");
+				Ok(target_pids.contains(&pid) || (descendants && is_descendant(pid, target_pids)))
+			} else {
+				Ok(false)
+			}
+		}
+		Some(WindowMatchProperty::Class(value) | WindowMatchProperty::ClassAny(value)) => {
+			let reply = conn.get_property(false, current, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 1024)?
+				.reply()?;
+			Ok(class_matches(&reply.value, value, true))
+		}
+		Some(WindowMatchProperty::Name(value)) => {
+			let len = value.len();
+			let result = conn.get_property(
+				false,
+				current,
+				AtomEnum::WM_NAME,
+				AtomEnum::STRING,
+				0,
+				len as u32)?;
+			let reply = result.reply()?;
+			let win_value = reply.value;
+			if win_value.len() == len {
+				Ok(compare_bytes(&win_value, value.as_bytes(), len))
+			} else {
+				Ok(false)
+			}
+		}
+		Some(WindowMatchProperty::Role(value)) => {
+			// no window has a role when the atom not exists
+			let Some(role_atom) = try_get_atom(conn, "WM_WINDOW_ROLE")? else {
+				return Ok(false);
+			};
+			let reply = conn.get_property(false, current, role_atom, AtomEnum::STRING, 0, 1024)?
+				.reply()?;
+			Ok(role_matches(&reply.value, value))
+		}
+		Some(WindowMatchProperty::Instance(value)) => {
+			let reply = conn.get_property(false, current, AtomEnum::WM_CLASS, AtomEnum::STRING, 0, 1024)?
+				.reply()?;
+			Ok(class_matches(&reply.value, value, false))
+		}
+		Some(WindowMatchProperty::NameStartsWith(value)) =>
+			Ok(window_name(conn, current)?.starts_with(value.as_str())),
+		Some(WindowMatchProperty::NameEndsWith(value)) =>
+			Ok(window_name(conn, current)?.ends_with(value.as_str())),
+		Some(WindowMatchProperty::Exe(value)) => {
+			let pid_atom = get_atom(conn, "_NET_WM_PID")?;
+			let pid = conn.get_property(false, current, pid_atom, AtomEnum::CARDINAL, 0, 1)?
+				.reply()?
+				.value32()
+				.and_then(|mut values| values.next());
+			let Some(pid) = pid else {
+				return Ok(false);
+			};
+			// process may be gone, or owned by other user
+			let Ok(exe) = fs::read_link(format!("/proc/{pid}/exe")) else {
+				return Ok(false);
+			};
+			Ok(exe_matches(&exe, value))
+		}
+	}
+}
+
+/// WM_CLASS value is instance and class, both null terminated, class is
+/// checked too when any_field
+fn class_matches(value: &[u8], expected: &str, any_field: bool) -> bool
+{
+	let mut parts = value.split(|b| *b == 0);
+	let instance = parts.next().unwrap_or_default();
+	if instance == expected.as_bytes() {
+		return true;
+	}
+	any_field && parts.next().is_some_and(|class| class == expected.as_bytes())
+}
+
+/// utf8 _NET_WM_NAME, or WM_NAME if not set
+fn window_name(conn: &RustConnection, win: Window) -> Result<String>
+{
+	let net_name_atom = get_atom(conn, "_NET_WM_NAME")?;
+	let utf8_atom = get_atom(conn, "UTF8_STRING")?;
+	let reply = conn.get_property(false, win, net_name_atom, utf8_atom, 0, 1024)?
+		.reply()?;
+	if !reply.value.is_empty() {
+		return Ok(String::from_utf8_lossy(&reply.value).into_owned());
+	}
+	let reply = conn.get_property(false, win, AtomEnum::WM_NAME, AtomEnum::ANY, 0, 1024)?
+		.reply()?;
+	// COMPOUND_TEXT is not decoded, ascii names still match
+	Ok(if reply.type_ == u32::from(AtomEnum::STRING) {
+		reply.value.iter().map(|b| *b as char).collect()
+	} else {
+		String::from_utf8_lossy(&reply.value).into_owned()
+	})
+}
+
+/// value with "/" is compared with the full path, otherwise the basename
+#[inline]
+fn exe_matches(exe: &Path, value: &str) -> bool
+{
+	if value.contains('/') {
+		exe == Path::new(value)
+	} else {
+		exe.file_name().is_some_and(|name| name == value)
+	}
+}
+
+/// any immediate child of window has the WM_CLASS
+fn child_class_matches(conn: &RustConnection, win: Window, class: &str) -> Result<bool>
+{
+	let children = conn.query_tree(win)?.reply()?.children;
+	let property = Some(WindowMatchProperty::Class(class.to_owned()));
+	for child in children {
+		// child may be destroyed already
+		if match_window(conn, child, &[], false, &property).unwrap_or(false) {
+			verbose!("child {child:#x} of window {win:#x} has class {class}");
+			return Ok(true);
+		}
+	}
+	Ok(false)
+}
+
+/// WM_WINDOW_ROLE value may be null terminated
+#[inline]
+fn role_matches(value: &[u8], role: &str) -> bool
+{
+	let value = value.strip_suffix(&[0]).unwrap_or(value);
+	value == role.as_bytes()
+}
+
+fn set_transient_for(conn: &RustConnection, win: Window, parent: Window) -> Result<()>
+{
+	match conn.get_geometry(parent)?.reply() {
+		Ok(_) => {}
+		Err(ReplyError::X11Error(_)) => return Err(anyhow!("Transient parent window not exists: {parent:#x}")),
+		Err(e) => return Err(e.into()),
+	}
+	let mut data = vec![];
+	push_u32(&mut data, parent);
+	conn.change_property(
+		PropMode::REPLACE,
+		win,
+		AtomEnum::WM_TRANSIENT_FOR,
+		AtomEnum::WINDOW,
+		32,
+		1,
+		&data,
+	)?.check()?;
+	Ok(())
+}
+
+#[inline]
+fn set_role(conn: &RustConnection, win: Window, role: &str) -> Result<()>
+{
+	let role_atom = get_or_create_atom(conn, "WM_WINDOW_ROLE")?;
+	conn.change_property(
+		PropMode::REPLACE,
+		win,
+		role_atom,
+		AtomEnum::STRING,
+		8,
+		role.len() as u32,
+		role.as_bytes(),
+	)?.check()?;
+	Ok(())
+}
+
+/// pid, class and name of window for logging
+fn describe_window(conn: &RustConnection, win: Window) -> Result<String>
+{
+	let pid_atom = get_atom(conn, "_NET_WM_PID")?;
+	let pid = conn.get_property(false, win, pid_atom, AtomEnum::CARDINAL, 0, 1)?
+		.reply()?
+		.value32()
+		.and_then(|mut values| values.next());
+	let text = |property: AtomEnum| -> Result<String> {
+		let reply = conn.get_property(false, win, property, AtomEnum::ANY, 0, 1024)?
+			.reply()?;
+		let values: Vec<String> = reply.value.split(|b| *b == 0)
+			.filter(|value| !value.is_empty())
+			.map(|value| String::from_utf8_lossy(value).into_owned())
+			.collect();
+		Ok(values.join(","))
+	};
+	let pid = pid.map_or_else(|| "none".to_owned(), |pid| pid.to_string());
+	Ok(format!("pid: {pid}, class: {}, name: {}", text(AtomEnum::WM_CLASS)?, text(AtomEnum::WM_NAME)?))
+}
+
+#[inline]
+fn compare_bytes(a: &[u8], b: &[u8], len: usize) -> bool
+{
+	for i in 0..len {
+		if a[i] != b[i] {
+			return false;
+		}
+	}
+	true
+}
+
+#[inline]
+fn push_u32(data: &mut Vec<u8>, value: u32)
+{
+	let bytes = value.to_le_bytes();
+	for byte in bytes {
+		data.push(byte);
+	}
+}
+
+#[inline]
+fn is_stdin(icon: &Path) -> bool
+{
+	icon == Path::new("-")
+}
+
+/// read icon file, or the data from stdin for "-"
+#[inline]
+fn read_icon_file<'a>(icon: &Path, stdin: Option<&'a [u8]>) -> Result<Cow<'a, [u8]>>
+{
+	if is_stdin(icon) {
+		let data = stdin.ok_or_else(|| anyhow!("No icon data read from stdin"))?;
+		Ok(Cow::Borrowed(data))
+	} else {
+		Ok(Cow::Owned(fs::read(icon)?))
+	}
+}
+
+#[inline]
+fn read_image(icon: &Path, stdin: Option<&[u8]>) -> Result<DynamicImage>
+{
+	let data = read_icon_file(icon, stdin)?;
+	if is_svg(icon, &data) {
+		render_svg(&data, None)
+	} else {
+		decode_image(&data)
+	}
+}
+
+#[inline]
+fn decode_image(data: &[u8]) -> Result<DynamicImage>
+{
+	if xpm::is_xpm(data) {
+		Ok(DynamicImage::ImageRgba8(xpm::load_xpm(data)?))
+	} else if xpm::is_xbm(data) {
+		Ok(DynamicImage::ImageRgba8(xpm::load_xbm(data)?))
+	} else {
+		Ok(image::load_from_memory(data)?)
+	}
+}
+
+fn load_icon(icon: &Path, options: &IconOptions) -> Result<IconData>
+{
+	let data = read_icon_file(icon, options.stdin)?;
+	if let Some(size) = options.square_size {
+		if !options.size_allowed(size, size) {
+			return Err(anyhow!("Icon size {size} out of size range"));
+		}
+		let image = if is_svg(icon, &data) {
+			render_svg(&data, Some(size))?
+		} else {
+			decode_image(&data)?
+		};
+		let mut icon_data = IconData::new();
+		icon_data.push_image(square_image(image, size))?;
+		return Ok(icon_data);
+	}
+	if is_svg(icon, &data) {
+		let mut icon_data = IconData::new();
+		for size in options.generated_sizes()? {
+			icon_data.push_image(render_svg(&data, Some(size))?)?;
+		}
+		return Ok(icon_data);
+	}
+	if is_ico(icon, &data) {
+		return load_ico(&data, options);
+	}
+	let mut image = decode_image(&data)?;
+	if let Some(scale) = &options.scale {
+		image = scale.apply(image);
+	}
+	let icon_data = build_icon_property(image, &options.generated_sizes()?)?;
+	// native size is used when smaller than all sizes
+	if let Some((width, height, _)) = icon_data.blocks().into_iter()
+		.find(|(width, height, _)| !options.size_allowed(*width, *height)) {
+		return Err(anyhow!("Icon size {width}x{height} out of size range"));
+	}
+	Ok(icon_data)
+}
+
+/// scale image into size x size, keep aspect ratio with transparent padding
+fn square_image(image: DynamicImage, size: u32) -> DynamicImage
+{
+	let image = image.resize(size, size, FilterType::Lanczos3);
+	if image.width() == size && image.height() == size {
+		return image;
+	}
+	let mut square = RgbaImage::new(size, size);
+	let x = (size - image.width()) / 2;
+	let y = (size - image.height()) / 2;
+	image::imageops::overlay(&mut square, &image.into_rgba8(), x as i64, y as i64);
+	DynamicImage::ImageRgba8(square)
+}
+
+#[inline]
+fn is_ico(icon: &Path, data: &[u8]) -> bool
+{
+	icon.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("ico"))
+		|| data.starts_with(&[0, 0, 1, 0])
+}
+
+/// pack every embedded image of ico in its native size, for duplicate
+/// sizes, the one with most bits per pixel is used
+fn load_ico(data: &[u8], options: &IconOptions) -> Result<IconData>
+{
+	let icon_dir = ico::IconDir::read(Cursor::new(data))?;
+	let mut entries: Vec<&ico::IconDirEntry> = vec![];
+	for entry in icon_dir.entries() {
+		if !options.size_allowed(entry.width(), entry.height()) {
+			verbose!("skip ico frame {}x{} out of size range", entry.width(), entry.height());
+			continue;
+		}
+		if let Some(existing) = entries.iter_mut()
+			.find(|e| e.width() == entry.width() && e.height() == entry.height()) {
+			if entry.bits_per_pixel() > existing.bits_per_pixel() {
+				*existing = entry;
+			}
+		} else {
+			entries.push(entry);
+		}
+	}
+	let mut icon_data = IconData::new();
+	for entry in entries {
+		let image = entry.decode()?;
+		let image = RgbaImage::from_raw(image.width(), image.height(), image.rgba_data().to_vec())
+			.ok_or_else(|| anyhow!("Invalid ico image: {}x{}", image.width(), image.height()))?;
+		icon_data.push_image(DynamicImage::ImageRgba8(image))?;
+	}
+	if icon_data.length == 0 {
+		return Err(anyhow!("No ico frame in size range"));
+	}
+	Ok(icon_data)
+}
+
+fn load_hidpi_icon(icon_1x: &Path, icon_2x: &Path, stdin: Option<&[u8]>)
+	-> Result<IconData>
+{
+	let image_1x = read_image(icon_1x, stdin)?;
+	let width = image_1x.width() * 2;
+	let height = image_1x.height() * 2;
+	let mut image_2x = read_image(icon_2x, stdin)?;
+	if image_2x.width() != width || image_2x.height() != height {
+		image_2x = image_2x.resize_exact(width, height, FilterType::Lanczos3);
+	}
+	let mut icon_data = IconData::new();
+	icon_data.push_image(image_1x)?;
+	icon_data.push_image(image_2x)?;
+	Ok(icon_data)
+}
+
+/// _NET_WM_ICON data as is, only the block sizes checked
+#[inline]
+fn load_raw_icon(data: Vec<u8>) -> Result<IconData>
+{
+	let mut rest = data.as_slice();
+	while !rest.is_empty() {
+		let Some(header) = rest.get(0..8) else {
+			return Err(anyhow!("Invalid raw icon, truncated header of {} bytes", rest.len()));
+		};
+		let width = u32::from_le_bytes(header[0..4].try_into().unwrap());
+		let height = u32::from_le_bytes(header[4..8].try_into().unwrap());
+		let end = (width as usize)
+			.checked_mul(height as usize)
+			.and_then(|pixels| pixels.checked_mul(4))
+			.and_then(|bytes| bytes.checked_add(8))
+			.filter(|end| *end <= rest.len() && width > 0 && height > 0)
+			.ok_or_else(|| anyhow!("Invalid raw icon of size {width}x{height}, {} bytes left", rest.len() - 8))?;
+		rest = &rest[end..];
+	}
+	if data.is_empty() {
+		return Err(anyhow!("Empty raw icon"));
+	}
+	let length = (data.len() / 4) as u32;
+	Ok(IconData { data, length })
+}
+
+fn icon_sizes(sizes: &[u32]) -> Vec<u32>
+{
+	let mut sizes = if sizes.is_empty() {
+		DEFAULT_ICON_SIZES.to_vec()
+	} else {
+		sizes.to_vec()
+	};
+	sizes.sort_unstable();
+	sizes.dedup();
+	sizes
+}
+
+/// detect by extension, or by sniffing svg tag in the leading bytes
+fn is_svg(icon: &Path, data: &[u8]) -> bool
+{
+	if let Some(ext) = icon.extension() {
+		if ext.eq_ignore_ascii_case("svg") || ext.eq_ignore_ascii_case("svgz") {
+			return true;
+		}
+	}
+	let head = &data[..data.len().min(1024)];
+	let head = String::from_utf8_lossy(head);
+	let head = head.trim_start_matches('\u{feff}').trim_start();
+	head.starts_with('<') && head.contains("<svg")
+}
+
+/// render svg into size x size square with aspect ratio kept, or in its own size when None
+#[cfg(feature = "svg")]
+fn render_svg(data: &[u8], size: Option<u32>) -> Result<DynamicImage>
+{
+	use image::RgbaImage;
+	use resvg::tiny_skia::{Pixmap, Transform};
+	use resvg::usvg::{Options, PostProcessingSteps, Tree, TreeParsing, TreePostProc};
+
+	let mut tree = Tree::from_data(data, &Options::default())?;
+	tree.postprocess(PostProcessingSteps::default());
+	let svg_width = tree.size.width();
+	let svg_height = tree.size.height();
+	let scale = match size {
+		Some(size) => size as f32 / svg_width.max(svg_height),
+		None => 1.0,
+	};
+	let width = ((svg_width * scale).round() as u32).max(1);
+	let height = ((svg_height * scale).round() as u32).max(1);
+	let mut pixmap = Pixmap::new(width, height)
+		.ok_or_else(|| anyhow!("Invalid svg size: {width}x{height}"))?;
+	resvg::render(&tree, Transform::from_scale(scale, scale), &mut pixmap.as_mut());
+	let mut image = RgbaImage::new(width, height);
+	for (pixel, color) in image.pixels_mut().zip(pixmap.pixels()) {
+		let color = color.demultiply();
+		pixel.0 = [color.red(), color.green(), color.blue(), color.alpha()];
+	}
+	Ok(DynamicImage::ImageRgba8(image))
+}
+
+#[cfg(not(feature = "svg"))]
+#[inline]
+fn render_svg(_data: &[u8], _size: Option<u32>) -> Result<DynamicImage>
+{
+	Err(anyhow!("SVG support not compiled in"))
+}
+
+/// single icon file is scaled to sizes, multiple files are packed in their
+/// native sizes, and for duplicate sizes, the first one is used
+fn load_icons(icons: &[PathBuf], options: &IconOptions) -> Result<IconData>
+{
+	if let [icon] = icons {
+		return load_icon(icon, options);
+	}
+	let mut icon_data = IconData::new();
+	let mut dimensions = vec![];
+	for icon in icons {
+		let image = read_image(icon, options.stdin)?;
+		let dimension = (image.width(), image.height());
+		if !options.size_allowed(dimension.0, dimension.1) {
+			verbose!("skip icon file {} of size {}x{} out of size range", icon.display(), dimension.0, dimension.1);
+			continue;
+		}
+		if dimensions.contains(&dimension) {
+			eprintln!("Duplicate icon size {}x{}, ignore icon file: {:#?}", dimension.0, dimension.1, icon);
+			continue;
+		}
+		dimensions.push(dimension);
+		icon_data.push_image(image)?;
+	}
+	if icon_data.data.is_empty() {
+		return Err(anyhow!("No icon file in size range"));
+	}
+	Ok(icon_data)
+}
+
+/// scale image to each size, the native size is used when all sizes are larger than image
+fn build_icon_property(image: DynamicImage, sizes: &[u32]) -> Result<IconData>
+{
+	let source_size = image.width().max(image.height());
+	let mut icon = IconData::new();
+	for size in icon_sizes(sizes) {
+		if size <= source_size {
+			icon.push_image(image.resize(size, size, FilterType::Lanczos3))?;
+		}
+	}
+	if icon.length == 0 {
+		icon.push_image(image)?;
+	}
+	Ok(icon)
+}
+
+/// delete _NET_WM_ICON, and icon pixmap and mask of WM_HINTS if set
+fn remove_icon(conn: &RustConnection, win: Window) -> Result<()>
+{
+	let icon_atom = get_atom(conn, "_NET_WM_ICON")?;
+	conn.delete_property(win, icon_atom)?.check()?;
+	if let Ok(hints) = WmHints::get(conn, win)?.reply() {
+		if hints.icon_pixmap.is_some() || hints.icon_mask.is_some() {
+			set_hint_pixmaps(conn, win, hints, None, None)?;
+		}
+	}
+	Ok(())
+}
+
+#[inline]
+fn set_icon(conn: &RustConnection, win: Window, icon: &IconData) -> Result<()>
+{
+	let set_icon_atom = get_atom(conn, "_NET_WM_ICON")?;
+	let chunks = icon_chunks(&icon.data, conn.maximum_request_bytes());
+	let count = chunks.len();
+	for (i, chunk) in chunks.into_iter().enumerate() {
+		let mode = if i == 0 { PropMode::REPLACE } else { PropMode::APPEND };
+		conn.change_property(
+			mode,
+			win,
+			set_icon_atom,
+			AtomEnum::CARDINAL,
+			32,
+			(chunk.len() / 4) as u32,
+			chunk,
+		)?.check()
+			.map_err(|e| anyhow!("Failed set icon of {} bytes, chunk {}/{count}: {e}",
+				icon.data.len(), i + 1))?;
+	}
+	Ok(())
+}
+
+/// split data into chunks fit in change_property requests, 32 bits aligned
+fn icon_chunks(data: &[u8], max_request_bytes: usize) -> Vec<&[u8]>
+{
+	// fixed part of ChangeProperty request
+	const HEADER_BYTES: usize = 24;
+	let chunk_size = (max_request_bytes.saturating_sub(HEADER_BYTES) / 4 * 4).max(4);
+	if data.is_empty() {
+		return vec![data];
+	}
+	data.chunks(chunk_size).collect()
+}
+
+/// max size of the pixmap for WM_HINTS, old window managers expect small icons
+const LEGACY_ICON_SIZE: u32 = 64;
+
+/// render the largest block not exceeds LEGACY_ICON_SIZE into pixmap and
+/// mask, existing flags of WM_HINTS kept
+fn set_legacy_icon(conn: &RustConnection, screen: &Screen, win: Window, icon: &IconData)
+	-> Result<()>
+{
+	let blocks = icon.blocks();
+	let (width, height, pixels) = blocks.iter()
+		.filter(|(width, height, _)| *width <= LEGACY_ICON_SIZE && *height <= LEGACY_ICON_SIZE)
+		.max_by_key(|(width, height, _)| width * height)
+		.or_else(|| blocks.iter().min_by_key(|(width, height, _)| width * height))
+		.copied()
+		.ok_or_else(|| anyhow!("No icon data for legacy icon"))?;
+	let setup = conn.setup();
+	let depth = screen.root_depth;
+	if pixmap_format(conn, depth)?.bits_per_pixel != 32 || setup.image_byte_order != ImageOrder::LSB_FIRST {
+		return Err(anyhow!("Unsupported pixmap format for legacy icon, depth {depth}"));
+	}
+	let pixmap = put_pixmap(conn, screen.root, depth, (width, height), pixels)?;
+	let mask = mask_pixmap(conn, screen.root, width, height, pixels)?;
+
+	let hints = WmHints::get(conn, win)?
+		.reply()
+		.unwrap_or_else(|_| WmHints::new());
+	set_hint_pixmaps(conn, win, hints, Some(pixmap), Some(mask))
+}
+
+/// icon mask of WM_HINTS from alpha channel of image file
+fn set_icon_mask(conn: &RustConnection, root: Window, win: Window, path: &Path,
+	stdin: Option<&[u8]>) -> Result<()>
+{
+	let image = read_image(path, stdin)?.into_rgba8();
+	let mask = mask_pixmap(conn, root, image.width(), image.height(), image.as_raw())?;
+	let hints = WmHints::get(conn, win)?
+		.reply()
+		.unwrap_or_else(|_| WmHints::new());
+	let pixmap = hints.icon_pixmap;
+	set_hint_pixmaps(conn, win, hints, pixmap, Some(mask))
+}
+
+/// pixmaps are kept by server after xicon exits, the ones created by xicon
+/// are recorded in _XICON_PIXMAPS of the window, and freed when replaced
+fn set_hint_pixmaps(conn: &RustConnection, win: Window, mut hints: WmHints,
+	icon_pixmap: Option<Pixmap>, icon_mask: Option<Pixmap>) -> Result<()>
+{
+	let created_atom = get_or_create_atom(conn, "_XICON_PIXMAPS")?;
+	let reply = conn.get_property(false, win, created_atom, AtomEnum::PIXMAP, 0, 2)?
+		.reply()?;
+	let created: Vec<Pixmap> = reply.value32()
+		.map(|values| values.collect())
+		.unwrap_or_default();
+	let old = [hints.icon_pixmap, hints.icon_mask];
+	hints.icon_pixmap = icon_pixmap;
+	hints.icon_mask = icon_mask;
+	hints.set(conn, win)?.check()?;
+
+	let current = [icon_pixmap, icon_mask];
+	for pixmap in old.into_iter().flatten() {
+		if created.contains(&pixmap) && !current.contains(&Some(pixmap)) {
+			if let Err(e) = conn.free_pixmap(pixmap)?.check() {
+				verbose!("failed free pixmap {pixmap:#x}: {e}");
+			}
+		}
+	}
+	// new ones, or old ones created by xicon and kept
+	let mut data = vec![];
+	let mut count = 0;
+	for pixmap in current.into_iter().flatten() {
+		if created.contains(&pixmap) || !old.contains(&Some(pixmap)) {
+			push_u32(&mut data, pixmap);
+			count += 1;
+		}
+	}
+	if count == 0 {
+		conn.delete_property(win, created_atom)?.check()?;
+	} else {
+		conn.change_property(
+			PropMode::REPLACE,
+			win,
+			created_atom,
+			AtomEnum::PIXMAP,
+			32,
+			count,
+			&data,
+		)?.check()?;
+	}
+	Ok(())
+}
+
+#[inline]
+fn pixmap_format(conn: &RustConnection, depth: u8) -> Result<&Format>
+{
+	conn.setup().pixmap_formats.iter()
+		.find(|format| format.depth == depth)
+		.ok_or_else(|| anyhow!("No pixmap format for depth {depth}"))
+}
+
+/// depth 1 pixmap from alpha channel of 4 bytes per pixel data
+fn mask_pixmap(conn: &RustConnection, root: Window, width: u32, height: u32, pixels: &[u8])
+	-> Result<Pixmap>
+{
+	let format = pixmap_format(conn, 1)?;
+	let lsb_first = conn.setup().bitmap_format_bit_order == ImageOrder::LSB_FIRST;
+	let data = bitmap_mask(width, height, pixels, format.scanline_pad as u32, lsb_first);
+	put_pixmap(conn, root, 1, (width, height), &data)
+}
+
+/// pixmap kept after xicon exited, with data in server format
+fn put_pixmap(conn: &RustConnection, root: Window, depth: u8, (width, height): (u32, u32),
+	data: &[u8]) -> Result<Pixmap>
+{
+	let format = if depth == 1 { ImageFormat::XY_PIXMAP } else { ImageFormat::Z_PIXMAP };
+	let pixmap = conn.generate_id()?;
+	conn.create_pixmap(depth, pixmap, root, width as u16, height as u16)?.check()?;
+	let gc = conn.generate_id()?;
+	conn.create_gc(gc, pixmap, &CreateGCAux::new())?.check()?;
+	conn.put_image(format, pixmap, gc, width as u16, height as u16, 0, 0, 0, depth, data)?
+		.check()?;
+	conn.free_gc(gc)?.check()?;
+	conn.set_close_down_mode(CloseDown::RETAIN_PERMANENT)?.check()?;
+	Ok(pixmap)
+}
+
+/// 1 bit mask from alpha channel of BGRA or RGBA pixels, rows padded to scanline_pad bits
+fn bitmap_mask(width: u32, height: u32, pixels: &[u8], scanline_pad: u32, lsb_first: bool)
+	-> Vec<u8>
+{
+	let row_bytes = (width.div_ceil(scanline_pad) * scanline_pad / 8) as usize;
+	let mut data = vec![0u8; row_bytes * height as usize];
+	for y in 0..height as usize {
+		for x in 0..width as usize {
+			let alpha = pixels[(y * width as usize + x) * 4 + 3];
+			if alpha >= 0x80 {
+				let bit = if lsb_first { x % 8 } else { 7 - x % 8 };
+				data[y * row_bytes + x / 8] |= 1 << bit;
+			}
+		}
+	}
+	data
+}
+
+/// read back width and height of the first icon in _NET_WM_ICON
+fn verify_icon(conn: &RustConnection, win: Window, expected_w: u32, expected_h: u32)
+	-> Result<bool>
+{
+	let icon_atom = get_atom(conn, "_NET_WM_ICON")?;
+	let reply = conn.get_property(
+		false,
+		win,
+		icon_atom,
+		AtomEnum::CARDINAL,
+		0, 2,
+	)?.reply()?;
+	let mut values = reply.value32()
+		.ok_or_else(|| anyhow!("Invalid icon property format: {}", reply.format))?;
+	match (values.next(), values.next()) {
+		(Some(width), Some(height)) => Ok(width == expected_w && height == expected_h),
+		_ => Ok(false),
+	}
+}
+
+#[inline]
+fn send_message(conn: &RustConnection, root: Window, win: Window,
+	msg_type: Atom, data: [u32; 5]) -> Result<()>
+{
+	let event = ClientMessageEvent::new(
+		32, win, msg_type, data);
+
+	conn.send_event(
+		true,
+		root,
+		EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+		event,
+	)?.check()?;
+	Ok(())
+}
+
+/// ICCCM iconify request, _NET_WM_STATE_HIDDEN is for window manager only
+#[inline]
+fn iconify(conn: &RustConnection, root: Window, win: Window) -> Result<()>
+{
+	const ICONIC_STATE: u32 = 3;
+
+	let change_state_atom = get_atom(conn, "WM_CHANGE_STATE")?;
+	send_message(conn, root, win, change_state_atom, [ICONIC_STATE, 0, 0, 0, 0])
+}
+
+#[inline]
+fn set_size(conn: &RustConnection, root: Window, win: Window,
+	size: &WindowSize, state: NetWmState) -> Result<()>
+{
+	if let WindowSize::Min = size {
+		return iconify(conn, root, win);
+	}
+	let mut atoms = [0; 2];
+	for (i, atom_name) in size.atom_names().iter().enumerate() {
+		atoms[i] = get_atom(conn, atom_name)?;
+	}
+	add_state(conn, root, win, state, atoms[0], atoms[1], 0, 0)
+}
+
+#[inline]
+fn set_above(conn: &RustConnection, root: Window, win: Window, state: NetWmState)
+	-> Result<()>
+{
+	let atom = get_atom(conn, "_NET_WM_STATE_ABOVE")?;
+	add_state(conn, root, win, state, atom, 0, 0, 0)
+}
+
+/// below state, and lower it for window managers ignore the state
+#[inline]
+fn set_below(conn: &RustConnection, root: Window, win: Window, state: NetWmState)
+	-> Result<()>
+{
+	let atom = get_atom(conn, "_NET_WM_STATE_BELOW")?;
+	add_state(conn, root, win, state, atom, 0, 0, 0)?;
+	let aux = ConfigureWindowAux::new().stack_mode(StackMode::BELOW);
+	conn.configure_window(win, &aux)?.check()?;
+	Ok(())
+}
+
+#[inline]
+fn set_modal(conn: &RustConnection, root: Window, win: Window, state: NetWmState)
+	-> Result<()>
+{
+	let atom = get_atom(conn, "_NET_WM_STATE_MODAL")?;
+	add_state(conn, root, win, state, atom, 0, 0, 0)
+}
+
+/// taskbars differ in which one they honor, so set both
+fn set_urgent(conn: &RustConnection, root: Window, win: Window, state: NetWmState)
+	-> Result<()>
+{
+	let atom = get_atom(conn, "_NET_WM_STATE_DEMANDS_ATTENTION")?;
+	add_state(conn, root, win, state, atom, 0, 0, 0)?;
+	set_urgency_hint(conn, win)
+}
+
+/// urgency flag of WM_HINTS, other hints kept
+fn set_urgency_hint(conn: &RustConnection, win: Window) -> Result<()>
+{
+	let mut hints = WmHints::get(conn, win)?
+		.reply()
+		.unwrap_or_else(|_| WmHints::new());
+	hints.urgent = true;
+	hints.set(conn, win)?.check()?;
+	Ok(())
+}
+
+/// request window manager to activate window, or set input focus directly
+/// when _NET_ACTIVE_WINDOW not supported
+fn focus_window(conn: &RustConnection, root: Window, win: Window) -> Result<()>
+{
+	const SOURCE_APPLICATION: u32 = 1;
+
+	let active_atom = get_atom(conn, "_NET_ACTIVE_WINDOW")?;
+	if is_supported(conn, root, active_atom)? {
+		send_message(conn, root, win, active_atom, [SOURCE_APPLICATION, x11rb::CURRENT_TIME, 0, 0, 0])
+	} else {
+		verbose!("_NET_ACTIVE_WINDOW not supported by window manager, set input focus");
+		conn.set_input_focus(InputFocus::PARENT, win, x11rb::CURRENT_TIME)?.check()?;
+		Ok(())
+	}
+}
+
+/// activity id of kwin for all activities, kwin checks the nil uuid, not a "\0" value
+const KDE_ALL_ACTIVITIES: &str = "00000000-0000-0000-0000-000000000000";
+
+/// comma separated activity ids
+fn kde_activities(cli: &Cli) -> Option<String>
+{
+	if cli.kde_all_activities {
+		return Some(KDE_ALL_ACTIVITIES.to_owned());
+	}
+	let activities: Vec<&str> = cli.kde_activities.as_ref()?
+		.iter()
+		.map(|activity| activity.trim())
+		.filter(|activity| !activity.is_empty())
+		.collect();
+	if activities.is_empty() {
+		Some(KDE_ALL_ACTIVITIES.to_owned())
+	} else {
+		Some(activities.join(","))
+	}
+}
+
+#[inline]
+fn set_kde_activities(conn: &RustConnection, win: Window, activities: &str) -> Result<()>
+{
+	let activities_atom = get_or_create_atom(conn, "_KDE_NET_WM_ACTIVITIES")?;
+	conn.change_property(
+		PropMode::REPLACE,
+		win,
+		activities_atom,
+		AtomEnum::STRING,
+		8,
+		activities.len() as u32,
+		activities.as_bytes(),
+	)?.check()?;
+	Ok(())
+}
+
+/// _NET_WM_DESKTOP value for all desktops
+const ALL_DESKTOPS: u32 = 0xFFFFFFFF;
+
+/// move window to desktop as pager does, index clamped to the last desktop,
+/// return the index used
+fn set_desktop(conn: &RustConnection, root: Window, win: Window, desktop: Desktop)
+	-> Result<u32>
+{
+	const SOURCE_PAGER: u32 = 2;
+
+	let mut index = match desktop {
+		Desktop::Index(index) => index,
+		Desktop::Current => get_cardinal(conn, root, "_NET_CURRENT_DESKTOP")?.unwrap_or(0),
+	};
+	if let Some(count) = get_cardinal(conn, root, "_NET_NUMBER_OF_DESKTOPS")? {
+		if count > 0 && index >= count {
+			eprintln!("Desktop {index} not exists, move to the last desktop {}", count - 1);
+			index = count - 1;
+		}
+	}
+	let desktop_atom = get_atom(conn, "_NET_WM_DESKTOP")?;
+	send_message(conn, root, win, desktop_atom, [index, SOURCE_PAGER, 0, 0, 0])?;
+	Ok(index)
+}
+
+/// index of desktop in _NET_DESKTOP_NAMES
+fn desktop_by_name(conn: &RustConnection, root: Window, name: &str) -> Result<u32>
+{
+	let names_atom = get_atom(conn, "_NET_DESKTOP_NAMES")?;
+	let utf8_atom = get_atom(conn, "UTF8_STRING")?;
+	let reply = conn.get_property(false, root, names_atom, utf8_atom, 0, u32::MAX)?
+		.reply()?;
+	let names = desktop_names(&reply.value);
+	find_desktop(&names, name)
+		.map(|index| index as u32)
+		.ok_or_else(|| anyhow!("No desktop named {name}, available: [{}]", names.join(", ")))
+}
+
+/// null-terminated utf8 names
+fn desktop_names(value: &[u8]) -> Vec<String>
+{
+	let value = value.strip_suffix(&[0]).unwrap_or(value);
+	if value.is_empty() {
+		return vec![];
+	}
+	value.split(|b| *b == 0)
+		.map(|name| String::from_utf8_lossy(name).into_owned())
+		.collect()
+}
+
+#[inline]
+fn find_desktop(names: &[String], name: &str) -> Option<usize>
+{
+	match name.strip_suffix('*') {
+		Some(prefix) => names.iter().position(|n| n.starts_with(prefix)),
+		None => names.iter().position(|n| n == name),
+	}
+}
+
+#[inline]
+fn get_cardinal(conn: &RustConnection, win: Window, name: &str) -> Result<Option<u32>>
+{
+	let atom = get_atom(conn, name)?;
+	Ok(conn.get_property(false, win, atom, AtomEnum::CARDINAL, 0, 1)?
+		.reply()?
+		.value32()
+		.and_then(|mut values| values.next()))
+}
+
+/// sticky state and all desktops, some window managers require both
+fn set_sticky(conn: &RustConnection, root: Window, win: Window, state: NetWmState)
+	-> Result<()>
+{
+	const SOURCE_APPLICATION: u32 = 1;
+
+	let sticky_atom = get_atom(conn, "_NET_WM_STATE_STICKY")?;
+	if is_supported(conn, root, sticky_atom)? {
+		add_state(conn, root, win, state, sticky_atom, 0, 0, 0)?;
+	} else {
+		verbose!("_NET_WM_STATE_STICKY not supported by window manager, set desktop only");
+	}
+	let desktop_atom = get_atom(conn, "_NET_WM_DESKTOP")?;
+	send_message(conn, root, win, desktop_atom, [ALL_DESKTOPS, SOURCE_APPLICATION, 0, 0, 0])
+}
+
+/// atom is listed in _NET_SUPPORTED of root window
+fn is_supported(conn: &RustConnection, root: Window, atom: Atom) -> Result<bool>
+{
+	let supported_atom = get_atom(conn, "_NET_SUPPORTED")?;
+	let reply = conn.get_property(false, root, supported_atom, AtomEnum::ATOM, 0, u32::MAX)?
+		.reply()?;
+	Ok(reply.value32().is_some_and(|mut atoms| atoms.any(|a| a == atom)))
+}
+
+#[inline]
+fn remove_decoration(conn: &RustConnection, win: Window) -> Result<()>
+{
+	const PROP_MOTIF_WM_HINTS_ELEMENTS: u32 = 5;
+	const MWM_HINTS_DECORATIONS: u32 = 1 << 1;
+
+	let decoration_property = get_atom(conn, "_MOTIF_WM_HINTS")?;
+	let mut data = vec![];
+	push_u32(&mut data, MWM_HINTS_DECORATIONS);
+	push_u32(&mut data, 0);
+	push_u32(&mut data, 0);
+	push_u32(&mut data, 0);
+	push_u32(&mut data, 0);
 
 	conn.change_property(
 		PropMode::REPLACE,
@@ -374,112 +2982,731 @@ fn remove_decoration(conn: &RustConnection, win: Window) -> Result<()>
 	Ok(())
 }
 
+/// types in order of preference
+#[inline]
+fn set_type(conn: &RustConnection, win: Window, win_types: &[WindowType]) -> Result<()>
+{
+	let win_type_prop = get_atom(conn, "_NET_WM_WINDOW_TYPE")?;
+	let mut data = vec![];
+	for win_type in win_types {
+		push_u32(&mut data, get_atom(conn, win_type.as_str())?);
+	}
+	conn.change_property(
+		PropMode::REPLACE,
+		win,
+		win_type_prop,
+		AtomEnum::ATOM,
+		32,
+		win_types.len() as u32,
+		&data,
+	)?.check()?;
+	Ok(())
+}
+
+#[inline]
+fn type_names(win_types: &[WindowType]) -> String
+{
+	let names: Vec<&str> = win_types.iter()
+		.map(WindowType::as_str)
+		.collect();
+	names.join(", ")
+}
+
+/// number part of geometry, error with the part name and whole geometry
+#[inline]
+fn parse_part<T: FromStr>(geometry: &str, name: &str, value: &str) -> Result<T>
+	where T::Err: fmt::Display
+{
+	value.parse()
+		.map_err(|e| anyhow!("Invalid geometry {name} \"{value}\" in {geometry}: {e}"))
+}
+
+#[inline]
+fn parse_geometry(geometry: &str) -> Result<WindowGeometry>
+{
+	let re = Regex::new(r"^(((\d+)(%?))?[xX]((\d+)(%?))?)?(([+-])(?:(c)|(\d+)(%?))([+-])(?:(c)|(\d+)(%?)))?$").unwrap();
+	let captures = re.captures(geometry)
+		.ok_or_else(|| anyhow!("Invalid geometry string: {geometry}"))?;
+	let length = |name: &str, value: usize, percent: usize| -> Result<Option<Length>> {
+		let (Some(value), Some(percent)) = (captures.get(value), captures.get(percent)) else {
+			return Ok(None);
+		};
+		let value: u32 = parse_part(geometry, name, value.as_str())?;
+		if percent.as_str().is_empty() {
+			Ok(Some(Length::Pixel(value)))
+		} else if value > 100 {
+			Err(anyhow!("Invalid geometry {name} \"{value}%\" in {geometry}: percentage over 100"))
+		} else {
+			Ok(Some(Length::Percent(value)))
+		}
+	};
+	let mut result = WindowGeometry {
+		offset: None,
+		size: None,
+	};
+	if captures.get(1).is_some() {
+		let w = length("width", 3, 4)?;
+		let h = length("height", 6, 7)?;
+		if w.is_none() && h.is_none() {
+			return Err(anyhow!("Invalid geometry string, no width or height: {geometry}"));
+		}
+		result.size = Some((w, h));
+	}
+	let offset = |name: &str, sign: usize, center: usize, value: usize, percent: usize| -> Result<Offset> {
+		if captures.get(center).is_some() {
+			return Ok(Offset::Center);
+		}
+		let length = length(name, value, percent)?
+			.ok_or_else(|| anyhow!("Invalid geometry string, no {name}: {geometry}"))?;
+		match captures.get(sign).map(|sign| sign.as_str()) {
+			Some("-") => Ok(Offset::End(length)),
+			_ => Ok(Offset::Start(length)),
+		}
+	};
+	if captures.get(8).is_some() {
+		let x = offset("x offset", 9, 10, 11, 12)?;
+		let y = offset("y offset", 13, 14, 15, 16)?;
+		result.offset = Some((x, y));
+	}
+	Ok(result)
+}
+
+fn set_opacity(conn: &RustConnection, win: Window, opacity: f64, recursive: bool)
+	-> Result<()>
+{
+	let opacity_atom = get_or_create_atom(conn, "_NET_WM_WINDOW_OPACITY")?;
+	let mut data = vec![];
+	push_u32(&mut data, (opacity * u32::MAX as f64).round() as u32);
+	let mut windows = if recursive {
+		descendants(conn, win)?
+	} else {
+		vec![win]
+	};
+	// some compositors only check the frame of window manager
+	let tree = conn.query_tree(win)?.reply()?;
+	if tree.parent != tree.root && tree.parent != win {
+		windows.push(tree.parent);
+	}
+	for win in windows {
+		// fully opaque is the same as no opacity set
+		if opacity >= 1.0 {
+			conn.delete_property(win, opacity_atom)?.check()?;
+			continue;
+		}
+		conn.change_property(
+			PropMode::REPLACE,
+			win,
+			opacity_atom,
+			AtomEnum::CARDINAL,
+			32,
+			1,
+			&data,
+		)?.check()?;
+	}
+	Ok(())
+}
+
+/// the window and all windows under it
+fn descendants(conn: &RustConnection, win: Window) -> Result<Vec<Window>>
+{
+	let mut windows = vec![win];
+	let mut i = 0;
+	while i < windows.len() {
+		let tree = conn.query_tree(windows[i])?.reply()?;
+		windows.extend(tree.children);
+		i += 1;
+	}
+	Ok(windows)
+}
+
+/// monitor of --monitor, or the whole screen
+#[inline]
+fn target_area(conn: &RustConnection, screen: &Screen, cli: &Cli) -> Result<Area>
+{
+	match &cli.monitor {
+		Some(monitor) => monitor_area(conn, screen.root, monitor),
+		None => Ok(Area::from(screen)),
+	}
+}
+
+/// maximize by configuring window directly, for no ewmh window manager,
+/// min sizes are not handled here
+fn fill_area(conn: &RustConnection, area: &Area, win: Window, size: &WindowSize) -> Result<()>
+{
+	let mut aux = ConfigureWindowAux::new();
+	if matches!(size, WindowSize::Max | WindowSize::MaxHorz | WindowSize::Fullscreen) {
+		aux = aux.x(area.x).width(area.width);
+	}
+	if matches!(size, WindowSize::Max | WindowSize::MaxVert | WindowSize::Fullscreen) {
+		aux = aux.y(area.y).height(area.height);
+	}
+	conn.configure_window(win, &aux)?.check()?;
+	verbose!("window configured to fill {area:?}");
+	Ok(())
+}
+
+/// area for geometry, workarea in the target area with --workarea
+fn geometry_area(conn: &RustConnection, screen: &Screen, win: Window, cli: &Cli) -> Result<Area>
+{
+	let area = target_area(conn, screen, cli)?;
+	if !cli.workarea {
+		return Ok(area);
+	}
+	// fall back silently without ewmh window manager
+	let Some(workarea) = workarea(conn, screen.root, win)? else {
+		return Ok(area);
+	};
+	match intersect(&area, &workarea) {
+		Some(area) => Ok(area),
+		None => {
+			verbose!("workarea {workarea:?} out of {area:?}, ignored");
+			Ok(area)
+		}
+	}
+}
+
+/// _NET_WORKAREA of window's desktop, or the current desktop for sticky or
+/// not placed window
+fn workarea(conn: &RustConnection, root: Window, win: Window) -> Result<Option<Area>>
+{
+	let desktop = match get_cardinal(conn, win, "_NET_WM_DESKTOP")? {
+		Some(desktop) if desktop != ALL_DESKTOPS => desktop,
+		_ => get_cardinal(conn, root, "_NET_CURRENT_DESKTOP")?.unwrap_or(0),
+	};
+	let workarea_atom = get_atom(conn, "_NET_WORKAREA")?;
+	let reply = conn.get_property(false, root, workarea_atom, AtomEnum::CARDINAL, desktop * 4, 4)?
+		.reply()?;
+	let values: Vec<u32> = reply.value32()
+		.map(|values| values.collect())
+		.unwrap_or_default();
+	let [x, y, width, height] = values[..] else {
+		verbose!("no _NET_WORKAREA for desktop {desktop}");
+		return Ok(None);
+	};
+	Ok(Some(Area { x: x as i32, y: y as i32, width, height }))
+}
+
+#[inline]
+fn intersect(a: &Area, b: &Area) -> Option<Area>
+{
+	let x = a.x.max(b.x);
+	let y = a.y.max(b.y);
+	let right = (a.x + a.width as i32).min(b.x + b.width as i32);
+	let bottom = (a.y + a.height as i32).min(b.y + b.height as i32);
+	if right <= x || bottom <= y {
+		return None;
+	}
+	Some(Area { x, y, width: (right - x) as u32, height: (bottom - y) as u32 })
+}
+
+fn resize_to_workarea(conn: &RustConnection, root: Window, win: Window) -> Result<()>
+{
+	let Some(workarea) = workarea(conn, root, win)? else {
+		verbose!("skip resize to workarea");
+		return Ok(());
+	};
+	let geometry = conn.get_geometry(win)?.reply()?;
+	// position relative to parent, which is the frame of window manager
+	let position = conn.translate_coordinates(win, root, 0, 0)?.reply()?;
+	let current = Area {
+		x: position.dst_x as i32,
+		y: position.dst_y as i32,
+		width: geometry.width as u32,
+		height: geometry.height as u32,
+	};
+	let fitted = fit_in_area(&current, &workarea);
+	if fitted == current {
+		return Ok(());
+	}
+	let aux = ConfigureWindowAux::new()
+		.x(fitted.x)
+		.y(fitted.y)
+		.width(fitted.width)
+		.height(fitted.height);
+	conn.configure_window(win, &aux)?.check()?;
+	verbose!("window resized from {current:?} to {fitted:?} in workarea {workarea:?}");
+	Ok(())
+}
+
+/// shrink rect to the size of area, and move it into area
+fn fit_in_area(rect: &Area, area: &Area) -> Area
+{
+	let width = rect.width.min(area.width);
+	let height = rect.height.min(area.height);
+	let x = rect.x.min(area.x + (area.width - width) as i32).max(area.x);
+	let y = rect.y.min(area.y + (area.height - height) as i32).max(area.y);
+	Area { x, y, width, height }
+}
+
+/// wait until window mapped, then no ConfigureNotify for a while, so the
+/// size queried for right and bottom offset is the final one
+fn wait_size_settled(conn: &RustConnection, win: Window, wait: Duration) -> Result<()>
+{
+	const SETTLE: Duration = Duration::from_millis(200);
+	const MAX_SETTLE: Duration = Duration::from_secs(2);
+
+	let aux = ChangeWindowAttributesAux::new()
+		.event_mask(EventMask::STRUCTURE_NOTIFY);
+	conn.change_window_attributes(win, &aux)?.check()?;
+	let mut mapped = conn.get_window_attributes(win)?.reply()?.map_state == MapState::VIEWABLE;
+	let mut last_change = elapsed();
+	let mut deadline = if mapped { last_change + MAX_SETTLE } else { wait.max(last_change + MAX_SETTLE) };
+	while elapsed() < deadline {
+		if mapped && elapsed() >= last_change + SETTLE {
+			break;
+		}
+		let Some(event) = conn.poll_for_event()? else {
+			thread::sleep(Duration::from_millis(20));
+			continue;
+		};
+		match event {
+			Event::MapNotify(event) if event.window == win => {
+				verbose!("window {win:#x} mapped, wait for size settled");
+				mapped = true;
+				last_change = elapsed();
+				deadline = last_change + MAX_SETTLE;
+			}
+			Event::ConfigureNotify(event) if event.window == win => {
+				verbose!("window {win:#x} configured to {}x{}", event.width, event.height);
+				last_change = elapsed();
+			}
+			Event::DestroyNotify(event) if event.window == win =>
+				return Err(anyhow!("Window {win:#x} destroyed before mapped")),
+			_ => {}
+		}
+	}
+	if !mapped {
+		verbose!("window {win:#x} not mapped, apply geometry anyway");
+	}
+	Ok(())
+}
+
+/// with hints, position and size are set in WM_NORMAL_HINTS too, for
+/// window managers without ewmh support
+#[inline]
+fn set_geometry(conn: &RustConnection, win: Window, aux: &ConfigureWindowAux, hints: bool)
+	-> Result<()>
+{
+	if hints {
+		set_geometry_hints(conn, win, aux)?;
+	}
+	conn.configure_window(win, aux)?.check()?;
+	Ok(())
+}
+
+/// left, right, top and bottom border widths of window manager's frame
+type FrameExtents = (u32, u32, u32, u32);
+
+/// _NET_FRAME_EXTENTS of window, not set before window managed, so requested
+/// by _NET_REQUEST_FRAME_EXTENTS and wait for it a while when specified
+fn frame_extents(conn: &RustConnection, root: Window, win: Window, request: bool)
+	-> Result<Option<FrameExtents>>
+{
+	const REQUEST_WAIT: Duration = Duration::from_millis(500);
+
+	let extents_atom = get_atom(conn, "_NET_FRAME_EXTENTS")?;
+	let read = || -> Result<Option<FrameExtents>> {
+		let values: Vec<u32> = conn.get_property(false, win, extents_atom, AtomEnum::CARDINAL, 0, 4)?
+			.reply()?
+			.value32()
+			.map(|values| values.collect())
+			.unwrap_or_default();
+		Ok(match values[..] {
+			[left, right, top, bottom] => Some((left, right, top, bottom)),
+			_ => None,
+		})
+	};
+	if let Some(extents) = read()? {
+		return Ok(Some(extents));
+	}
+	let request_atom = get_atom(conn, "_NET_REQUEST_FRAME_EXTENTS")?;
+	if !request || !is_supported(conn, root, request_atom)? {
+		return Ok(None);
+	}
+	let aux = ChangeWindowAttributesAux::new()
+		.event_mask(EventMask::PROPERTY_CHANGE | EventMask::STRUCTURE_NOTIFY);
+	conn.change_window_attributes(win, &aux)?.check()?;
+	send_message(conn, root, win, request_atom, [0; 5])?;
+	let until = elapsed() + REQUEST_WAIT;
+	while elapsed() < until {
+		match conn.poll_for_event()? {
+			Some(Event::PropertyNotify(event)) if event.window == win && event.atom == extents_atom =>
+				return read(),
+			Some(_) => {}
+			None => thread::sleep(Duration::from_millis(20)),
+		}
+	}
+	verbose!("no _NET_FRAME_EXTENTS for window {win:#x}, position client window");
+	Ok(None)
+}
+
+/// win_gravity of WM_NORMAL_HINTS, 0 to let window manager use it when not set
+#[inline]
+fn window_gravity(conn: &RustConnection, win: Window) -> Result<u32>
+{
+	let gravity = WmSizeHints::get_normal_hints(conn, win)?
+		.reply()
+		.ok()
+		.and_then(|hints| hints.win_gravity);
+	Ok(gravity.map_or(0, u32::from))
+}
+
+fn net_moveresize_window(conn: &RustConnection, root: Window, win: Window, gravity: u32,
+	aux: &ConfigureWindowAux) -> Result<()>
+{
+	let moveresize_atom = get_atom(conn, "_NET_MOVERESIZE_WINDOW")?;
+	let flags = moveresize_flags(gravity, aux);
+	send_message(conn, root, win, moveresize_atom, [
+		flags,
+		aux.x.unwrap_or(0) as u32,
+		aux.y.unwrap_or(0) as u32,
+		aux.width.unwrap_or(0),
+		aux.height.unwrap_or(0),
+	])
+}
+
+/// gravity in bits 0-7, presence of x, y, width and height in bits 8-11, and
+/// source indication in bits 12-13
+#[inline]
+fn moveresize_flags(gravity: u32, aux: &ConfigureWindowAux) -> u32
+{
+	const SOURCE_PAGER: u32 = 2;
+
+	let mut flags = (gravity & 0xff) | (SOURCE_PAGER << 12);
+	for (i, present) in [aux.x.is_some(), aux.y.is_some(), aux.width.is_some(), aux.height.is_some()]
+		.into_iter()
+		.enumerate() {
+		if present {
+			flags |= 1 << (8 + i);
+		}
+	}
+	flags
+}
+
+/// PPosition and PSize of WM_NORMAL_HINTS, other hints kept
+fn set_geometry_hints(conn: &RustConnection, win: Window, aux: &ConfigureWindowAux)
+	-> Result<()>
+{
+	let mut hints = WmSizeHints::get_normal_hints(conn, win)?
+		.reply()
+		.unwrap_or_else(|_| WmSizeHints::new());
+	if let (Some(x), Some(y)) = (aux.x, aux.y) {
+		hints.position = Some((WmSizeHintsSpecification::ProgramSpecified, x, y));
+	}
+	if aux.width.is_some() || aux.height.is_some() {
+		let geometry = conn.get_geometry(win)?.reply()?;
+		let width = aux.width.unwrap_or(geometry.width as u32);
+		let height = aux.height.unwrap_or(geometry.height as u32);
+		hints.size = Some((WmSizeHintsSpecification::ProgramSpecified, width as i32, height as i32));
+	}
+	hints.set_normal_hints(conn, win)?.check()?;
+	Ok(())
+}
+
+/// position and size to configure, resolved within area
+/// with frame extents, offsets are for the frame
+fn geometry_aux(conn: &RustConnection, area: &Area, win: Window, geometry: Option<&str>,
+	scale: Option<f32>, center: bool, extents: Option<FrameExtents>) -> Result<ConfigureWindowAux>
+{
+	let mut geometry = match geometry {
+		Some(geometry) => parse_geometry(geometry)?,
+		None => WindowGeometry { size: None, offset: None },
+	};
+	if let Some(factor) = scale {
+		geometry = geometry.scale(factor);
+	}
+	let (width, height) = geometry.size.map_or((None, None), |(w, h)| (
+		w.map(|w| w.resolve(area.width)),
+		h.map(|h| h.resolve(area.height)),
+	));
+	let mut aux = ConfigureWindowAux::new();
+	aux.width = width;
+	aux.height = height;
+	// current window size, queried only once when needed
+	let mut orig_win_size = None;
+	let mut window_size = || -> Result<(u32, u32)> {
+		if let Some(size) = orig_win_size {
+			return Ok(size);
+		}
+		let reply = conn.get_geometry(win)?.reply()?;
+		let size = (reply.width as u32, reply.height as u32);
+		orig_win_size = Some(size);
+		Ok(size)
+	};
+	let offset = if center { Some((Offset::Center, Offset::Center)) } else { geometry.offset };
+	if let Some((x_offset, y_offset)) = offset {
+		let width = match width {
+			Some(width) => width,
+			None if x_offset.needs_size() => window_size()?.0,
+			None => 0,
+		};
+		let height = match height {
+			Some(height) => height,
+			None if y_offset.needs_size() => window_size()?.1,
+			None => 0,
+		};
+		let static_gravity = extents.is_some()
+			&& window_gravity(conn, win)? == u32::from(Gravity::STATIC);
+		let (left, right, top, bottom) = extents.unwrap_or_default();
+		let x = x_offset.resolve_framed(area.x, area.width, width, (left, right), static_gravity);
+		let y = y_offset.resolve_framed(area.y, area.height, height, (top, bottom), static_gravity);
+		aux = aux.x(x).y(y);
+	}
+	Ok(aux)
+}
+
+#[inline]
+fn set_border_width(conn: &RustConnection, win: Window, width: u32) -> Result<()>
+{
+	let aux = ConfigureWindowAux::new().border_width(width);
+	conn.configure_window(win, &aux)?.check()?;
+	Ok(())
+}
+
+/// update min/max size of WM_NORMAL_HINTS, other hints kept
+fn set_size_hints(conn: &RustConnection, win: Window, min_size: Option<(u32, u32)>,
+	max_size: Option<(u32, u32)>) -> Result<()>
+{
+	let mut hints = WmSizeHints::get_normal_hints(conn, win)?
+		.reply()
+		.unwrap_or_else(|_| WmSizeHints::new());
+	if let Some((width, height)) = min_size {
+		hints.min_size = Some((width as i32, height as i32));
+	}
+	if let Some((width, height)) = max_size {
+		hints.max_size = Some((width as i32, height as i32));
+	}
+	hints.set_normal_hints(conn, win)?.check()?;
+	Ok(())
+}
+
+/// area of monitor with the index, output name, or "primary", which is the
+/// first monitor when no primary one set
+fn monitor_area(conn: &RustConnection, root: Window, monitor: &str) -> Result<Area>
+{
+	let reply = conn.randr_get_monitors(root, true)?.reply()?;
+	let primary = monitor == "primary";
+	let mut names = vec![];
+	for (index, info) in reply.monitors.iter().enumerate() {
+		let name = get_atom_name(conn, info.name)?;
+		if monitor == index.to_string() || monitor == name || (primary && info.primary) {
+			return Ok(Area::from(info));
+		}
+		names.push(format!("{index}: {name}"));
+	}
+	if let (true, Some(info)) = (primary, reply.monitors.first()) {
+		verbose!("no primary monitor set, use the first one");
+		return Ok(Area::from(info));
+	}
+	Err(anyhow!("Monitor not found: {monitor}, available monitors: [{}]", names.join(", ")))
+}
+
 #[inline]
-fn set_type(conn: &RustConnection, win: Window, win_type: &WindowType) -> Result<()>
+fn hide_taskbar_icon(conn: &RustConnection, root: Window, win: Window,
+	state: NetWmState) -> Result<()>
 {
-	let win_type_prop = get_atom(conn, "_NET_WM_WINDOW_TYPE")?;
-	let win_type_value = get_atom(conn, win_type.as_str())?;
+	let atom = get_atom(conn, "_NET_WM_STATE_SKIP_TASKBAR")?;
+	add_state(conn, root, win, state, atom, 0, 0, 0)
+}
+
+#[inline]
+fn hide_pager(conn: &RustConnection, root: Window, win: Window,
+	state: NetWmState) -> Result<()>
+{
+	let atom = get_atom(conn, "_NET_WM_STATE_SKIP_PAGER")?;
+	add_state(conn, root, win, state, atom, 0, 0, 0)
+}
+
+#[inline]
+fn set_shade(conn: &RustConnection, root: Window, win: Window, state: NetWmState)
+	-> Result<()>
+{
+	const ATOM_NAME: &str = "_NET_WM_STATE_SHADED";
+	if let Some(atom) = try_get_atom(conn, ATOM_NAME)? {
+		if !is_supported(conn, root, atom)? {
+			verbose!("{ATOM_NAME} not listed in _NET_SUPPORTED, window manager may ignore it");
+		}
+		add_state(conn, root, win, state, atom, 0, 0, 0)
+	} else {
+		eprintln!("Failed create atom: {ATOM_NAME}, window manager may not support shading.");
+		Ok(())
+	}
+}
+
+/// _NET_WM_STATE atom, states are changed in the property directly instead
+/// of requesting window manager when direct
+#[derive(Clone, Copy, Debug)]
+struct NetWmState {
+	atom: Atom,
+	direct: bool,
+}
+
+#[inline]
+#[allow(clippy::too_many_arguments)]
+fn add_state(conn: &RustConnection, root: Window, win: Window, state: NetWmState,
+	v1: u32, v2: u32, v3: u32, v4: u32) -> Result<()>
+{
+	if state.direct {
+		return update_state(conn, win, state.atom, _NET_WM_STATE_ADD, &[v1, v2]);
+	}
+	change_state(conn, root, win, state.atom, _NET_WM_STATE_ADD, v1, v2, v3, v4)
+}
+
+#[inline]
+#[allow(clippy::too_many_arguments)]
+fn change_state(conn: &RustConnection, root: Window, win: Window, state_atom: Atom,
+	action: u32, v1: u32, v2: u32, v3: u32, v4: u32) -> Result<()>
+{
+	send_message(conn, root, win, state_atom, [
+		action,
+		v1, v2, v3, v4
+	])?;
+	Ok(())
+}
+
+/// two states per message
+fn change_states(conn: &RustConnection, root: Window, win: Window, state: NetWmState,
+	action: u32, states: &[WindowState]) -> Result<()>
+{
+	const SOURCE_APPLICATION: u32 = 1;
+
+	for pair in states.chunks(2) {
+		let first = get_atom(conn, pair[0].atom_name())?;
+		let second = match pair.get(1) {
+			Some(state) => get_atom(conn, state.atom_name())?,
+			None => 0,
+		};
+		if state.direct {
+			update_state(conn, win, state.atom, action, &[first, second])?;
+		} else if action == _NET_WM_STATE_ADD {
+			add_state(conn, root, win, state, first, second, SOURCE_APPLICATION, 0)?;
+		} else {
+			change_state(conn, root, win, state.atom, action, first, second, SOURCE_APPLICATION, 0)?;
+		}
+	}
+	Ok(())
+}
+
+/// add, remove or toggle atoms in _NET_WM_STATE directly, window manager
+/// will not notice the change of a managed window
+fn update_state(conn: &RustConnection, win: Window, state_atom: Atom, action: u32,
+	atoms: &[Atom]) -> Result<()>
+{
+	let reply = conn.get_property(false, win, state_atom, AtomEnum::ATOM, 0, u32::MAX)?
+		.reply()?;
+	let existing: Vec<Atom> = reply.value32()
+		.map(|values| values.collect())
+		.unwrap_or_default();
+	let states = updated_states(&existing, action, atoms);
+	if states == existing {
+		return Ok(());
+	}
 	let mut data = vec![];
-	push_u32(&mut data, win_type_value);
+	for atom in &states {
+		push_u32(&mut data, *atom);
+	}
 	conn.change_property(
 		PropMode::REPLACE,
 		win,
-		win_type_prop,
+		state_atom,
 		AtomEnum::ATOM,
 		32,
-		1,
+		states.len() as u32,
 		&data,
 	)?.check()?;
 	Ok(())
 }
 
-#[inline]
-fn parse_geometry(geometry: &str) -> Result<WindowGeometry>
+/// states after the _NET_WM_STATE action, order of existing ones kept
+fn updated_states(existing: &[Atom], action: u32, atoms: &[Atom]) -> Vec<Atom>
 {
-	let re = Regex::new(r"^((\d+)[xX](\d+))?(([+-])(\d+)([+-])(\d+))?$").unwrap();
-	let captures = re.captures(geometry)
-		.unwrap_or_else(|| panic!("Invalid geometry string: {geometry}"));
-	let mut geometry = WindowGeometry {
-		offset: None,
-		size: None,
-	};
-	if let (Some(w), Some(h)) = (captures.get(2), captures.get(3)) {
-		let w: u32 = w.as_str().parse()?;
-		let h: u32 = h.as_str().parse()?;
-		geometry.size = Some((w, h));
-	}
-	if let (Some(xs), Some(x), Some(ys), Some(y)) = (captures.get(5), captures.get(6), captures.get(7), captures.get(8)) {
-		let x: i32 = x.as_str().parse()?;
-		let xs = xs.as_str() == "-";
-		let y: i32 = y.as_str().parse()?;
-		let ys = ys.as_str() == "-";
-		geometry.offset = Some((xs, x, ys, y));
+	let mut states = existing.to_vec();
+	for atom in atoms {
+		if *atom == x11rb::NONE {
+			continue;
+		}
+		let exists = states.contains(atom);
+		if action == _NET_WM_STATE_REMOVE || (action == _NET_WM_STATE_TOGGLE && exists) {
+			states.retain(|state| state != atom);
+		} else if !exists {
+			states.push(*atom);
+		}
 	}
-	Ok(geometry)
+	states
 }
 
-#[inline]
-fn set_geometry(conn: &RustConnection, screen: &Screen, win: Window, geometry: &str) -> Result<()>
+fn print_property(conn: &RustConnection, win: Window, name: &str) -> Result<()>
 {
-	let geometry = parse_geometry(geometry)?;
-	let mut aux = ConfigureWindowAux::new();
-	if let Some(size) = geometry.size {
-		aux = aux.width(size.0).height(size.1);
-	}
-	if let Some(offset) = geometry.offset {
-		let xs = offset.0;
-		let mut x = offset.1;
-		let ys = offset.2;
-		let mut y = offset.3;
-		let mut orig_win_size = None;
-		if xs {
-			let width = if let Some(size) = geometry.size {
-				size.0 as i32
-			} else {
-				let size = conn.get_geometry(win)?
-					.reply()?;
-				let ow = size.width;
-				let oh = size.height;
-				orig_win_size = Some((ow, oh));
-				ow as i32
-			};
-			x = screen.width_in_pixels as i32 - x - width;
-		}
-		if ys {
-			let height = if let Some(size) = geometry.size {
-				size.1 as i32
-			} else if let Some((_, oh)) = orig_win_size {
-				oh as i32
-			} else {
-				conn.get_geometry(win)?
-					.reply()?.height as i32
-			};
-			y = screen.height_in_pixels as i32 - y - height;
+	let atom = match try_get_atom(conn, name)? {
+		Some(atom) => atom,
+		None => {
+			println!("{name}: not found");
+			return Ok(());
 		}
-		aux = aux.x(x).y(y);
+	};
+	let reply = conn.get_property(false, win, atom, AtomEnum::ANY, 0, u32::MAX)?
+		.reply()?;
+	if reply.type_ == x11rb::NONE {
+		println!("{name}: not found");
+	} else {
+		let type_name = get_atom_name(conn, reply.type_)?;
+		println!("{name}({type_name}) = {}", format_property(conn, &reply)?);
 	}
-	conn.configure_window(win, &aux)?.check()?;
 	Ok(())
 }
 
+fn format_property(conn: &RustConnection, reply: &GetPropertyReply) -> Result<String>
+{
+	let type_ = reply.type_;
+	let utf8_string = try_get_atom(conn, "UTF8_STRING")?;
+	let text = if type_ == AtomEnum::STRING.into() {
+		// null-separated string list
+		reply.value.split(|b| *b == 0)
+			.filter(|buf| !buf.is_empty())
+			.map(|buf| format!("\"{}\"", buf.escape_ascii()))
+			.collect::<Vec<_>>()
+			.join(", ")
+	} else if Some(type_) == utf8_string {
+		reply.value.split(|b| *b == 0)
+			.filter(|buf| !buf.is_empty())
+			.map(|buf| format!("\"{}\"", String::from_utf8_lossy(buf)))
+			.collect::<Vec<_>>()
+			.join(", ")
+	} else if type_ == AtomEnum::ATOM.into() {
+		let mut names = vec![];
+		for atom in reply.value32().into_iter().flatten() {
+			names.push(get_atom_name(conn, atom)?);
+		}
+		names.join(", ")
+	} else if type_ == AtomEnum::WINDOW.into() {
+		reply.value32().into_iter().flatten()
+			.map(|win| format!("{win:#x}"))
+			.collect::<Vec<_>>()
+			.join(", ")
+	} else if type_ == AtomEnum::CARDINAL.into() || type_ == AtomEnum::INTEGER.into() {
+		format_numbers(reply, |n| n.to_string())
+	} else {
+		format_numbers(reply, |n| format!("{n:#x}"))
+	};
+	Ok(text)
+}
+
 #[inline]
-fn hide_taskbar_icon(conn: &RustConnection, root: Window, win: Window,
-	state_atom: Atom) -> Result<()>
+fn format_numbers(reply: &GetPropertyReply, f: impl Fn(u32) -> String) -> String
 {
-	let atom = get_atom(conn, "_NET_WM_STATE_SKIP_TASKBAR")?;
-	add_state(conn, root, win, state_atom, atom, 0, 0, 0)
+	let numbers: Vec<u32> = match reply.format {
+		8 => reply.value8().into_iter().flatten().map(u32::from).collect(),
+		16 => reply.value16().into_iter().flatten().map(u32::from).collect(),
+		_ => reply.value32().into_iter().flatten().collect(),
+	};
+	numbers.into_iter().map(f).collect::<Vec<_>>().join(", ")
 }
 
 #[inline]
-fn add_state(conn: &RustConnection, root: Window, win: Window, state_atom: Atom,
-	v1: u32, v2: u32, v3: u32, v4: u32) -> Result<()>
+fn get_atom_name(conn: &RustConnection, atom: Atom) -> Result<String>
 {
-	const _NET_WM_STATE_ADD: u32 = 1;
-	send_message(conn, root, win, state_atom, [
-		_NET_WM_STATE_ADD,
-		v1, v2, v3, v4
-	])?;
-	Ok(())
+	let reply = conn.get_atom_name(atom)?.reply()?;
+	Ok(String::from_utf8_lossy(&reply.name).into_owned())
 }
 
 #[inline]
@@ -491,24 +3718,589 @@ fn get_atom(conn: &RustConnection, atom_name: &str) -> Result<Atom>
 		.atom)
 }
 
+/// intern atom, create it when not exists, for properties that may not be
+/// used by any client yet
+#[inline]
+fn get_or_create_atom(conn: &RustConnection, atom_name: &str) -> Result<Atom>
+{
+	Ok(conn.intern_atom(false, atom_name.as_bytes())?.reply()?.atom)
+}
+
+/// like get_atom, but return None instead of panic when atom not exists
+#[inline]
+fn try_get_atom(conn: &RustConnection, atom_name: &str) -> Result<Option<Atom>>
+{
+	let atom = match conn.intern_atom(true, atom_name.as_bytes())?.reply() {
+		Ok(reply) => reply.atom,
+		Err(_) => return Ok(None),
+	};
+	if atom == x11rb::NONE {
+		Ok(None)
+	} else {
+		Ok(Some(atom))
+	}
+}
+
 #[cfg(test)]
 mod test {
-	use crate::parse_geometry;
+	use image::{DynamicImage, GrayAlphaImage, GrayImage, ImageBuffer, RgbImage, Rgba, RgbaImage};
+	use std::env;
+	use std::io::Cursor;
+	use std::path::{Path, PathBuf};
+	use std::time::Duration;
+	use image::ImageOutputFormat;
+	use x11rb::protocol::xproto::ConfigureWindowAux;
+	use crate::{bitmap_mask, build_icon_property, class_matches, descendant_pids, desktop_names, exe_matches, find_desktop, fit_in_area, intersect, moveresize_flags, parse_desktop, icon_chunks, json_array, latin1, load_hidpi_icon, load_ico, load_icon, load_icons, load_raw_icon, parse_class, parse_geometry, parse_parent_pid, parse_icon_scale, parse_opacity, parse_size, parse_wait, parse_window_id, role_matches, updated_states, Area, Desktop, IconData, IconOptions, IconScale, Length, Offset, WindowInfo, WindowMatchProperty, _NET_WM_STATE_ADD, _NET_WM_STATE_REMOVE, _NET_WM_STATE_TOGGLE};
 
 	#[test]
 	fn test_parse_geometry()
 	{
 		let g = parse_geometry("200x200+100-100").unwrap();
-		assert_eq!(g.size.unwrap(), (200, 200));
-		assert_eq!(g.offset.unwrap(), (false, 100, true, 100));
+		assert_eq!(g.size.unwrap(), (Some(Length::Pixel(200)), Some(Length::Pixel(200))));
+		assert_eq!(g.offset.unwrap(), (Offset::Start(Length::Pixel(100)), Offset::End(Length::Pixel(100))));
 		let g = parse_geometry("200x200").unwrap();
-		assert_eq!(g.size.unwrap(), (200, 200));
+		assert_eq!(g.size.unwrap(), (Some(Length::Pixel(200)), Some(Length::Pixel(200))));
 		assert!(g.offset.is_none());
 		let g = parse_geometry("+100-100").unwrap();
 		assert!(g.size.is_none());
-		assert_eq!(g.offset.unwrap(), (false, 100, true, 100));
+		assert_eq!(g.offset.unwrap(), (Offset::Start(Length::Pixel(100)), Offset::End(Length::Pixel(100))));
 		let g = parse_geometry("-100-100").unwrap();
 		assert!(g.size.is_none());
-		assert_eq!(g.offset.unwrap(), (true, 100, true, 100));
+		assert_eq!(g.offset.unwrap(), (Offset::End(Length::Pixel(100)), Offset::End(Length::Pixel(100))));
+		let g = parse_geometry("50%x50%").unwrap();
+		assert_eq!(g.size.unwrap(), (Some(Length::Percent(50)), Some(Length::Percent(50))));
+		assert!(g.offset.is_none());
+		let g = parse_geometry("800x50%-0+0").unwrap();
+		assert_eq!(g.size.unwrap(), (Some(Length::Pixel(800)), Some(Length::Percent(50))));
+		assert_eq!(g.offset.unwrap(), (Offset::End(Length::Pixel(0)), Offset::Start(Length::Pixel(0))));
+		assert_eq!(Length::Percent(50).resolve(1920), 960);
+		assert_eq!(Length::Pixel(800).resolve(1920), 800);
+		assert!(parse_geometry("50%%x50").is_err());
+		assert!(parse_geometry("garbage").is_err());
+		let g = parse_geometry("800x50%+10-5").unwrap().scale(2.0);
+		assert_eq!(g.size.unwrap(), (Some(Length::Pixel(1600)), Some(Length::Percent(50))));
+		assert_eq!(g.offset.unwrap(), (Offset::Start(Length::Pixel(20)), Offset::End(Length::Pixel(10))));
+		let g = parse_geometry("600x").unwrap();
+		assert_eq!(g.size.unwrap(), (Some(Length::Pixel(600)), None));
+		let g = parse_geometry("X50%-0+0").unwrap();
+		assert_eq!(g.size.unwrap(), (None, Some(Length::Percent(50))));
+		assert_eq!(g.offset.unwrap(), (Offset::End(Length::Pixel(0)), Offset::Start(Length::Pixel(0))));
+		assert!(parse_geometry("x").is_err());
+		let g = parse_geometry("50%x100%+25%-10").unwrap();
+		assert_eq!(g.size.unwrap(), (Some(Length::Percent(50)), Some(Length::Percent(100))));
+		assert_eq!(g.offset.unwrap(), (Offset::Start(Length::Percent(25)), Offset::End(Length::Pixel(10))));
+		let g = parse_geometry("-0%+50%").unwrap().scale(2.0);
+		assert!(g.size.is_none());
+		assert_eq!(g.offset.unwrap(), (Offset::End(Length::Percent(0)), Offset::Start(Length::Percent(50))));
+		assert!(parse_geometry("+25%%+0").is_err());
+		assert!(parse_geometry("101%x10").is_err());
+		assert!(parse_geometry("5000000%x10").is_err());
+		assert!(parse_geometry("+0+200%").is_err());
+		assert!(parse_geometry("100%x100%+100%+0").is_ok());
+		let g = parse_geometry("800x600+c+c").unwrap();
+		assert_eq!(g.offset.unwrap(), (Offset::Center, Offset::Center));
+		let g = parse_geometry("+c+100").unwrap();
+		assert!(g.size.is_none());
+		assert_eq!(g.offset.unwrap(), (Offset::Center, Offset::Start(Length::Pixel(100))));
+		let g = parse_geometry("50%x-10%-c").unwrap().scale(2.0);
+		assert_eq!(g.offset.unwrap(), (Offset::End(Length::Percent(10)), Offset::Center));
+		assert!(parse_geometry("+c").is_err());
+		assert!(parse_geometry("+c%+0").is_err());
+		assert!(parse_geometry("+cc+0").is_err());
+		assert!(parse_geometry("x+0+0").is_err());
+		let error = parse_geometry("99999999999x10").unwrap_err().to_string();
+		assert!(error.contains("99999999999"), "{error}");
+	}
+
+	#[test]
+	fn test_resolve_offset()
+	{
+		assert_eq!(Offset::Start(Length::Pixel(10)).resolve(100, 1000, 200), 110);
+		assert_eq!(Offset::Start(Length::Percent(50)).resolve(100, 1000, 200), 600);
+		assert_eq!(Offset::End(Length::Pixel(10)).resolve(100, 1000, 200), 890);
+		assert_eq!(Offset::End(Length::Percent(10)).resolve(0, 1000, 200), 700);
+		assert_eq!(Offset::Center.resolve(100, 1000, 200), 500);
+		assert_eq!(Offset::Center.resolve(0, 1000, 1200), -100);
+		assert!(!Offset::Start(Length::Pixel(0)).needs_size());
+		assert!(Offset::End(Length::Pixel(0)).needs_size());
+		assert!(Offset::Center.needs_size());
+		// frame of 5 on left, 20 on right
+		assert_eq!(Offset::Start(Length::Pixel(0)).resolve_framed(0, 1000, 200, (5, 20), false), 0);
+		assert_eq!(Offset::Start(Length::Pixel(0)).resolve_framed(0, 1000, 200, (5, 20), true), 5);
+		assert_eq!(Offset::End(Length::Pixel(0)).resolve_framed(0, 1000, 200, (5, 20), false), 775);
+		assert_eq!(Offset::End(Length::Pixel(0)).resolve_framed(0, 1000, 200, (5, 20), true), 780);
+		assert_eq!(Offset::Center.resolve_framed(0, 1000, 200, (0, 0), true), 400);
+	}
+
+	#[test]
+	fn test_parse_window_id()
+	{
+		assert_eq!(parse_window_id("0x1a00004"), Ok(0x1a00004));
+		assert_eq!(parse_window_id("27262980"), Ok(27262980));
+		assert!(parse_window_id("0xzz").is_err());
+	}
+
+	#[test]
+	fn test_desktop_names()
+	{
+		let names = desktop_names("web\0mail box\0音乐\0".as_bytes());
+		assert_eq!(names, ["web", "mail box", "音乐"]);
+		assert!(desktop_names(b"").is_empty());
+		assert_eq!(desktop_names(b"a\0b"), ["a", "b"]);
+		assert_eq!(find_desktop(&names, "mail box"), Some(1));
+		assert_eq!(find_desktop(&names, "mail*"), Some(1));
+		assert_eq!(find_desktop(&names, "音乐"), Some(2));
+		assert_eq!(find_desktop(&names, "mail"), None);
+	}
+
+	#[test]
+	fn test_moveresize_flags()
+	{
+		let aux = ConfigureWindowAux::new().x(10).y(-20);
+		assert_eq!(moveresize_flags(0, &aux), 0x2300);
+		let aux = ConfigureWindowAux::new().x(10).y(20).width(100).height(50);
+		assert_eq!(moveresize_flags(10, &aux), 0x2f0a);
+		assert_eq!(moveresize_flags(0, &ConfigureWindowAux::new()), 0x2000);
+	}
+
+	#[test]
+	fn test_fit_in_area()
+	{
+		let workarea = Area { x: 0, y: 30, width: 1920, height: 1050 };
+		let rect = Area { x: 100, y: 100, width: 800, height: 600 };
+		assert_eq!(fit_in_area(&rect, &workarea), rect);
+		let rect = Area { x: 100, y: 0, width: 2000, height: 1200 };
+		assert_eq!(fit_in_area(&rect, &workarea), Area { x: 0, y: 30, width: 1920, height: 1050 });
+		let rect = Area { x: 1500, y: 900, width: 800, height: 600 };
+		assert_eq!(fit_in_area(&rect, &workarea), Area { x: 1120, y: 480, width: 800, height: 600 });
+	}
+
+	#[test]
+	fn test_intersect()
+	{
+		let monitor = Area { x: 1920, y: 0, width: 1920, height: 1080 };
+		let workarea = Area { x: 0, y: 30, width: 3840, height: 1050 };
+		assert_eq!(intersect(&monitor, &workarea), Some(Area { x: 1920, y: 30, width: 1920, height: 1050 }));
+		let workarea = Area { x: 0, y: 0, width: 1920, height: 1080 };
+		assert_eq!(intersect(&monitor, &workarea), None);
+	}
+
+	#[test]
+	fn test_parse_desktop()
+	{
+		assert_eq!(parse_desktop("2"), Ok(Desktop::Index(2)));
+		assert_eq!(parse_desktop("current"), Ok(Desktop::Current));
+		assert!(parse_desktop("-1").is_err());
+		assert!(parse_desktop("last").is_err());
+	}
+
+	#[test]
+	fn test_parse_match_property()
+	{
+		assert!(matches!(WindowMatchProperty::try_from("class=XTerm"), Ok(WindowMatchProperty::Class(c)) if c == "XTerm"));
+		assert!(matches!(WindowMatchProperty::try_from("name=a=b"), Ok(WindowMatchProperty::Name(n)) if n == "a=b"));
+		assert!(matches!(WindowMatchProperty::try_from("role=browser"), Ok(WindowMatchProperty::Role(r)) if r == "browser"));
+		assert!(matches!(WindowMatchProperty::try_from("exe=/usr/bin/xterm"), Ok(WindowMatchProperty::Exe(e)) if e == "/usr/bin/xterm"));
+		assert!(matches!(WindowMatchProperty::try_from("name_startswith=Mozilla"), Ok(WindowMatchProperty::NameStartsWith(n)) if n == "Mozilla"));
+		assert!(matches!(WindowMatchProperty::try_from("name_endswith=- Firefox"), Ok(WindowMatchProperty::NameEndsWith(n)) if n == "- Firefox"));
+		assert!(matches!(WindowMatchProperty::try_from("class_any=XTerm"), Ok(WindowMatchProperty::ClassAny(c)) if c == "XTerm"));
+		assert!(matches!(WindowMatchProperty::try_from("instance=xterm"), Ok(WindowMatchProperty::Instance(i)) if i == "xterm"));
+		assert!(WindowMatchProperty::try_from("name_contains=x").is_err());
+		assert!(WindowMatchProperty::try_from("title=xterm").is_err());
+		assert!(WindowMatchProperty::try_from("class=").is_err());
+	}
+
+	#[test]
+	fn test_parse_opacity()
+	{
+		assert_eq!(parse_opacity("0.85"), Ok(0.85));
+		assert_eq!(parse_opacity("1"), Ok(1.0));
+		assert!(parse_opacity("1.5").is_err());
+		assert!(parse_opacity("-0.1").is_err());
+		assert!(parse_opacity("half").is_err());
+	}
+
+	#[test]
+	fn test_parse_wait()
+	{
+		assert_eq!(parse_wait("10"), Ok(Duration::from_secs(10)));
+		assert_eq!(parse_wait("1.5"), Ok(Duration::from_millis(1500)));
+		assert!(parse_wait("-1").is_err());
+		assert!(parse_wait("soon").is_err());
+	}
+
+	#[test]
+	#[cfg(target_os = "linux")]
+	fn test_descendant_pids()
+	{
+		let mut child = std::process::Command::new("sleep").arg("5").spawn().unwrap();
+		let pids = descendant_pids(std::process::id());
+		child.kill().unwrap();
+		child.wait().unwrap();
+		assert_eq!(pids[0], std::process::id());
+		assert!(pids.contains(&child.id()));
+	}
+
+	#[test]
+	fn test_parse_parent_pid()
+	{
+		assert_eq!(parse_parent_pid("1234 (bash) S 1000 1234 1234 0"), Some(1000));
+		assert_eq!(parse_parent_pid("1234 (my (odd) prog) R 42 1234"), Some(42));
+		assert_eq!(parse_parent_pid("garbage"), None);
+	}
+
+	#[test]
+	fn test_updated_states()
+	{
+		assert_eq!(updated_states(&[1, 2], _NET_WM_STATE_ADD, &[2, 3]), vec![1, 2, 3]);
+		assert_eq!(updated_states(&[1, 2], _NET_WM_STATE_REMOVE, &[1, 0]), vec![2]);
+		assert_eq!(updated_states(&[1, 2], _NET_WM_STATE_TOGGLE, &[2, 3]), vec![1, 3]);
+		assert_eq!(updated_states(&[], _NET_WM_STATE_ADD, &[0, 0]), Vec::<u32>::new());
+	}
+
+	#[test]
+	fn test_json_array()
+	{
+		assert_eq!(json_array(&[]), "[]");
+		assert_eq!(json_array(&["_NET_WM_STATE".to_owned(), "a\"b\\\n\x01".to_owned()]),
+			"[\"_NET_WM_STATE\",\"a\\\"b\\\\\\n\\u0001\"]");
+	}
+
+	#[test]
+	fn test_window_info_json()
+	{
+		let info = WindowInfo {
+			id: 0x1a00004,
+			pid: Some(42),
+			class: "xterm,XTerm".to_owned(),
+			name: "a \"b\"".to_owned(),
+			geometry: (-10, 20, 800, 600),
+		};
+		assert_eq!(info.json(), "{\"id\":27262980,\"pid\":42,\"class\":\"xterm,XTerm\",\"name\":\"a \\\"b\\\"\",\"x\":-10,\"y\":20,\"width\":800,\"height\":600}");
+		let info = WindowInfo { pid: None, ..info };
+		assert!(info.json().contains("\"pid\":null"));
+	}
+
+	#[test]
+	fn test_latin1()
+	{
+		assert_eq!(latin1("xterm"), b"xterm");
+		assert_eq!(latin1("caf\u{e9} \u{7ec8}\u{7aef}"), b"caf\xe9 ??");
+	}
+
+	#[test]
+	fn test_parse_class()
+	{
+		assert_eq!(parse_class("term,XTerm"), Ok(("term".to_owned(), "XTerm".to_owned())));
+		assert_eq!(parse_class("java"), Ok(("java".to_owned(), "java".to_owned())));
+		assert!(parse_class("a,").is_err());
+		assert!(parse_class("a,b,c").is_err());
+	}
+
+	#[test]
+	fn test_exe_matches()
+	{
+		let exe = Path::new("/usr/bin/xterm");
+		assert!(exe_matches(exe, "xterm"));
+		assert!(exe_matches(exe, "/usr/bin/xterm"));
+		assert!(!exe_matches(exe, "/bin/xterm"));
+		assert!(!exe_matches(exe, "xter"));
+	}
+
+	#[test]
+	fn test_class_matches()
+	{
+		let value = b"xterm\0XTerm\0";
+		assert!(class_matches(value, "xterm", true));
+		assert!(class_matches(value, "XTerm", true));
+		assert!(class_matches(value, "xterm", false));
+		assert!(!class_matches(value, "XTerm", false));
+		assert!(!class_matches(value, "", true));
+		assert!(!class_matches(value, "xter", true));
+		assert!(!class_matches(b"", "xterm", true));
+	}
+
+	#[test]
+	fn test_role_matches()
+	{
+		assert!(role_matches(b"browser", "browser"));
+		assert!(role_matches(b"browser\0", "browser"));
+		assert!(!role_matches(b"browser-window", "browser"));
+		assert!(!role_matches(b"", "browser"));
+	}
+
+	#[test]
+	fn test_parse_size()
+	{
+		assert_eq!(parse_size("800x600"), Ok((800, 600)));
+		assert_eq!(parse_size("800X600"), Ok((800, 600)));
+		assert!(parse_size("800x").is_err());
+	}
+
+	#[test]
+	fn test_parse_icon_scale()
+	{
+		assert_eq!(parse_icon_scale("256"), Ok(IconScale::Max(256, false)));
+		assert_eq!(parse_icon_scale("128!"), Ok(IconScale::Max(128, true)));
+		assert_eq!(parse_icon_scale("64x32"), Ok(IconScale::Exact(64, 32)));
+		assert!(parse_icon_scale("0").is_err());
+		assert!(parse_icon_scale("big").is_err());
+	}
+
+	#[test]
+	fn test_load_scaled_icon()
+	{
+		let path = std::env::temp_dir().join("xicon_test_scale.png");
+		RgbaImage::new(64, 32).save(&path).unwrap();
+		let options = IconOptions { sizes: &[16, 128], scale: Some(IconScale::Max(32, false)), ..Default::default() };
+		let icon = load_icon(&path, &options).unwrap();
+		assert_eq!(icon.first_size(), (16, 8));
+		assert_eq!(icon.length, 16 * 8 + 2);
+		let options = IconOptions { sizes: &[256], scale: Some(IconScale::Max(128, false)), ..Default::default() };
+		assert_eq!(load_icon(&path, &options).unwrap().first_size(), (64, 32));
+		let options = IconOptions { sizes: &[256], scale: Some(IconScale::Max(128, true)), ..Default::default() };
+		assert_eq!(load_icon(&path, &options).unwrap().first_size(), (128, 64));
+		let options = IconOptions { sizes: &[256], scale: Some(IconScale::Exact(20, 20)), ..Default::default() };
+		assert_eq!(load_icon(&path, &options).unwrap().first_size(), (20, 20));
+		std::fs::remove_file(&path).unwrap();
+	}
+
+	#[test]
+	fn test_bitmap_mask()
+	{
+		let icon = build_icon_property(DynamicImage::ImageRgba8(RgbaImage::from_fn(10, 2, |x, _| {
+			if x % 3 == 0 { Rgba([0, 0, 0, 255]) } else { Rgba([0, 0, 0, 0]) }
+		})), &[]).unwrap();
+		let blocks = icon.blocks();
+		assert_eq!(blocks.len(), 1);
+		let (width, height, pixels) = blocks[0];
+		assert_eq!((width, height), (10, 2));
+		let mask = bitmap_mask(width, height, pixels, 32, true);
+		assert_eq!(mask.len(), 8);
+		assert_eq!(&mask[..4], &[0b0100_1001, 0b0000_0010, 0, 0]);
+		let mask = bitmap_mask(width, height, pixels, 8, false);
+		assert_eq!(&mask[..2], &[0b1001_0010, 0b0100_0000]);
+	}
+
+	#[test]
+	fn test_icon_chunks()
+	{
+		let data = vec![0u8; 100];
+		let chunks = icon_chunks(&data, 24 + 42);
+		assert_eq!(chunks.iter().map(|c| c.len()).collect::<Vec<_>>(), vec![40, 40, 20]);
+		assert_eq!(icon_chunks(&data, 1 << 20).len(), 1);
+		assert_eq!(icon_chunks(&[], 1 << 20).len(), 1);
+	}
+
+	#[test]
+	fn test_build_icon_property()
+	{
+		let image = DynamicImage::ImageRgba8(RgbaImage::new(64, 32));
+		let icon = build_icon_property(image.clone(), &[]).unwrap();
+		// 16x8, 24x12, 32x16, 48x24, 64x32
+		assert_eq!(icon.length, 128 + 288 + 512 + 1152 + 2048 + 2 * 5);
+		assert_eq!(icon.data.len(), icon.length as usize * 4);
+		let icon = build_icon_property(image.clone(), &[32, 16, 32]).unwrap();
+		assert_eq!(icon.length, 128 + 512 + 2 * 2);
+		let icon = build_icon_property(image, &[128]).unwrap();
+		assert_eq!(icon.length, 64 * 32 + 2);
+	}
+
+	#[test]
+	fn test_load_icons()
+	{
+		let dir = env::temp_dir();
+		let mut icons = vec![];
+		for (name, size) in [("16", 16), ("32", 32), ("32_dup", 32)] {
+			let path = dir.join(format!("xicon_test_load_icons_{name}.png"));
+			RgbaImage::new(size, size).save(&path).unwrap();
+			icons.push(path);
+		}
+		let icon = load_icons(&icons, &IconOptions::default()).unwrap();
+		assert_eq!(icon.length, 16 * 16 + 32 * 32 + 2 * 2);
+		assert_eq!(icon.data.len(), icon.length as usize * 4);
+		assert_eq!(&icon.data[0..8], &[16, 0, 0, 0, 16, 0, 0, 0]);
+		let offset = (16 * 16 + 2) * 4;
+		assert_eq!(&icon.data[offset..offset + 8], &[32, 0, 0, 0, 32, 0, 0, 0]);
+		let options = IconOptions { max_size: Some(8), ..Default::default() };
+		assert!(load_icons(&icons, &options).is_err());
+	}
+
+	#[test]
+	fn test_load_icon_size_range()
+	{
+		let path = env::temp_dir().join("xicon_test_load_icon_size_range.png");
+		RgbaImage::new(64, 64).save(&path).unwrap();
+		let options = IconOptions { min_size: Some(30), ..Default::default() };
+		let icon = load_icon(&path, &options).unwrap();
+		let sizes: Vec<u32> = icon.blocks().iter().map(|(width, _, _)| *width).collect();
+		assert_eq!(sizes, vec![32, 48, 64]);
+		// only the native size left, which is out of range
+		let options = IconOptions { min_size: Some(100), ..Default::default() };
+		assert!(load_icon(&path, &options).is_err());
+		let options = IconOptions { min_size: Some(300), ..Default::default() };
+		assert!(load_icon(&path, &options).is_err());
+	}
+
+	#[test]
+	fn test_load_non_rgba_icon()
+	{
+		let dir = env::temp_dir();
+		let rgb = dir.join("xicon_test_load_rgb_icon.png");
+		RgbImage::from_pixel(8, 6, [255, 0, 0].into()).save(&rgb).unwrap();
+		let gray = dir.join("xicon_test_load_gray_icon.png");
+		GrayImage::from_pixel(8, 6, [128].into()).save(&gray).unwrap();
+
+		let icon = load_icon(&rgb, &IconOptions::default()).unwrap();
+		assert_eq!(icon.length, 8 * 6 + 2);
+		assert_eq!(icon.data.len(), icon.length as usize * 4);
+		assert_eq!(&icon.data[8..12], &[0, 0, 255, 255]);
+		let icon = load_icon(&gray, &IconOptions::default()).unwrap();
+		assert_eq!(icon.length, 8 * 6 + 2);
+		assert_eq!(icon.data.len(), icon.length as usize * 4);
+		assert_eq!(&icon.data[8..12], &[128, 128, 128, 255]);
+	}
+
+	#[test]
+	fn test_load_square_icon()
+	{
+		let path = env::temp_dir().join("xicon_test_load_square_icon.png");
+		RgbaImage::from_pixel(64, 32, [255, 0, 0, 255].into()).save(&path).unwrap();
+		let options = IconOptions { square_size: Some(16), ..Default::default() };
+		let icon = load_icon(&path, &options).unwrap();
+		assert_eq!(icon.length, 16 * 16 + 2);
+		assert_eq!(&icon.data[0..8], &[16, 0, 0, 0, 16, 0, 0, 0]);
+		// first row is padding, the 16x8 image starts at row 4
+		assert_eq!(&icon.data[8..12], &[0, 0, 0, 0]);
+		let offset = 8 + 16 * 4 * 4;
+		assert_eq!(&icon.data[offset..offset + 4], &[0, 0, 255, 255]);
+	}
+
+	#[test]
+	fn test_load_stdin_icon()
+	{
+		let mut data = vec![];
+		RgbaImage::new(8, 8)
+			.write_to(&mut Cursor::new(&mut data), ImageOutputFormat::Png)
+			.unwrap();
+		let options = IconOptions { stdin: Some(&data), ..Default::default() };
+		let icon = load_icon(&PathBuf::from("-"), &options).unwrap();
+		assert_eq!(icon.length, 8 * 8 + 2);
+		assert!(load_icon(&PathBuf::from("-"), &IconOptions::default()).is_err());
+	}
+
+	#[test]
+	fn test_load_hidpi_icon()
+	{
+		let dir = env::temp_dir();
+		let icon_1x = dir.join("xicon_test_load_hidpi_icon_1x.png");
+		RgbaImage::new(16, 16).save(&icon_1x).unwrap();
+		let icon_2x = dir.join("xicon_test_load_hidpi_icon_2x.png");
+		RgbaImage::new(40, 40).save(&icon_2x).unwrap();
+		let icon = load_hidpi_icon(&icon_1x, &icon_2x, None).unwrap();
+		assert_eq!(icon.length, 16 * 16 + 32 * 32 + 2 * 2);
+		let offset = (16 * 16 + 2) * 4;
+		assert_eq!(&icon.data[offset..offset + 8], &[32, 0, 0, 0, 32, 0, 0, 0]);
+	}
+
+	#[test]
+	fn test_non_rgba_argb()
+	{
+		fn argb(image: DynamicImage) -> Vec<u32>
+		{
+			let icon = build_icon_property(image, &[]).unwrap();
+			icon.data[8..].chunks(4)
+				.map(|b| u32::from_le_bytes(b.try_into().unwrap()))
+				.collect()
+		}
+		let rgb = RgbImage::from_raw(2, 1, vec![255, 0, 0, 0, 0, 255]).unwrap();
+		assert_eq!(argb(DynamicImage::ImageRgb8(rgb)), [0xffff0000, 0xff0000ff]);
+		let l8 = GrayImage::from_raw(2, 1, vec![0, 128]).unwrap();
+		assert_eq!(argb(DynamicImage::ImageLuma8(l8)), [0xff000000, 0xff808080]);
+		let la8 = GrayAlphaImage::from_raw(2, 1, vec![255, 0, 64, 128]).unwrap();
+		assert_eq!(argb(DynamicImage::ImageLumaA8(la8)), [0x00ffffff, 0x80404040]);
+		let rgba16 = ImageBuffer::<Rgba<u16>, _>::from_raw(2, 1, vec![65535, 0, 0, 65535, 0, 65535, 0, 0]).unwrap();
+		assert_eq!(argb(DynamicImage::ImageRgba16(rgba16)), [0xffff0000, 0x0000ff00]);
+	}
+
+	#[test]
+	fn test_load_raw_icon()
+	{
+		let mut icon = IconData::new();
+		icon.push_image(DynamicImage::ImageRgba8(RgbaImage::new(2, 2))).unwrap();
+		icon.push_image(DynamicImage::ImageRgba8(RgbaImage::new(1, 3))).unwrap();
+		let raw = load_raw_icon(icon.data.clone()).unwrap();
+		assert_eq!(raw.data, icon.data);
+		assert_eq!(raw.length, icon.length);
+		assert!(load_raw_icon(icon.data[..icon.data.len() - 1].to_vec()).is_err());
+		assert!(load_raw_icon(vec![1, 0, 0, 0]).is_err());
+		assert!(load_raw_icon(vec![255; 8]).is_err());
+		assert!(load_raw_icon(vec![]).is_err());
+	}
+
+	#[test]
+	fn test_load_ico()
+	{
+		let mut icon_dir = ico::IconDir::new(ico::ResourceType::Icon);
+		for size in [16, 32] {
+			let image = ico::IconImage::from_rgba_data(size, size, vec![255; (size * size * 4) as usize]);
+			icon_dir.add_entry(ico::IconDirEntry::encode(&image).unwrap());
+		}
+		let mut data = vec![];
+		icon_dir.write(&mut data).unwrap();
+		let icon = load_ico(&data, &IconOptions::default()).unwrap();
+		assert_eq!(icon.length, 16 * 16 + 32 * 32 + 2 * 2);
+		assert_eq!(&icon.data[0..8], &[16, 0, 0, 0, 16, 0, 0, 0]);
+		let offset = (16 * 16 + 2) * 4;
+		assert_eq!(&icon.data[offset..offset + 8], &[32, 0, 0, 0, 32, 0, 0, 0]);
+		let options = IconOptions { min_size: Some(20), ..Default::default() };
+		let icon = load_ico(&data, &options).unwrap();
+		assert_eq!(icon.length, 32 * 32 + 2);
+		let options = IconOptions { max_size: Some(8), ..Default::default() };
+		assert!(load_ico(&data, &options).is_err());
+	}
+
+	#[test]
+	fn test_load_ico_dedup_and_png()
+	{
+		let mut icon_dir = ico::IconDir::new(ico::ResourceType::Icon);
+		let bmp = |size: u32| {
+			let image = ico::IconImage::from_rgba_data(size, size, vec![255; (size * size * 4) as usize]);
+			ico::IconDirEntry::encode_as_bmp(&image).unwrap()
+		};
+		let png = |size: u32| {
+			let image = ico::IconImage::from_rgba_data(size, size, [10, 20, 30, 128].repeat((size * size) as usize));
+			ico::IconDirEntry::encode_as_png(&image).unwrap()
+		};
+		// the 1 bit bmp 32x32 should be replaced by the 32 bits png one
+		icon_dir.add_entry(bmp(16));
+		icon_dir.add_entry(bmp(32));
+		icon_dir.add_entry(png(32));
+		icon_dir.add_entry(png(48));
+		let mut data = vec![];
+		icon_dir.write(&mut data).unwrap();
+		let icon = load_ico(&data, &IconOptions::default()).unwrap();
+		assert_eq!(icon.length, 16 * 16 + 32 * 32 + 48 * 48 + 2 * 3);
+		let offset = (16 * 16 + 2) * 4;
+		assert_eq!(&icon.data[offset..offset + 8], &[32, 0, 0, 0, 32, 0, 0, 0]);
+		assert_eq!(&icon.data[offset + 8..offset + 12], &[30, 20, 10, 128]);
+		let offset = offset + (32 * 32 + 2) * 4;
+		assert_eq!(&icon.data[offset..offset + 8], &[48, 0, 0, 0, 48, 0, 0, 0]);
+	}
+
+	#[test]
+	#[cfg(feature = "svg")]
+	fn test_load_svg_icon()
+	{
+		let path = env::temp_dir().join("xicon_test_load_svg_icon");
+		std::fs::write(&path, r#"<svg xmlns="http://www.w3.org/2000/svg" width="20" height="10">
+			<rect width="20" height="10" fill="red"/>
+		</svg>"#).unwrap();
+		let options = IconOptions { sizes: &[16, 32], ..Default::default() };
+		let icon = load_icon(&path, &options).unwrap();
+		// 16x8 and 32x16
+		assert_eq!(icon.length, 16 * 8 + 32 * 16 + 2 * 2);
+		// first pixel in BGRA
+		assert_eq!(&icon.data[8..12], &[0, 0, 255, 255]);
 	}
 }
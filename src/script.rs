@@ -0,0 +1,76 @@
+#[cfg(feature = "lua")]
+use std::fs;
+use std::path::Path;
+use anyhow::Result;
+use x11rb::protocol::xproto::{Screen, Window};
+use crate::NetWmState;
+use x11rb::rust_connection::RustConnection;
+
+#[cfg(not(feature = "lua"))]
+#[inline]
+pub fn run_script(_conn: &RustConnection, _screen: &Screen, _win: Window, _state: NetWmState,
+	_script: &Path) -> Result<()>
+{
+	Err(anyhow::anyhow!("Lua support not compiled in"))
+}
+
+/// run lua script with the matched window as global "window", and xicon
+/// operations in global table "xicon"
+#[cfg(feature = "lua")]
+pub fn run_script(conn: &RustConnection, screen: &Screen, win: Window, state: NetWmState,
+	script: &Path) -> Result<()>
+{
+	use mlua::Lua;
+	use crate::{add_state, geometry_aux, get_atom, load_icons, match_window, parse_match_property, set_above, set_geometry, set_icon, set_opacity, set_title, set_type, Area, IconOptions, WindowType};
+
+	let code = fs::read_to_string(script)?;
+	let lua = Lua::new();
+	let root = screen.root;
+	lua.scope(|scope| {
+		let xicon = lua.create_table()?;
+		xicon.set("set_icon", scope.create_function(|_, (win, path): (Window, String)| {
+			let icon = load_icons(&[path.into()], &IconOptions::default()).map_err(lua_error)?;
+			set_icon(conn, win, &icon).map_err(lua_error)
+		})?)?;
+		xicon.set("set_above", scope.create_function(|_, win: Window| {
+			set_above(conn, root, win, state).map_err(lua_error)
+		})?)?;
+		xicon.set("add_state", scope.create_function(|_, (win, name): (Window, String)| {
+			let atom = get_atom(conn, &name).map_err(lua_error)?;
+			add_state(conn, root, win, state, atom, 0, 0, 0).map_err(lua_error)
+		})?)?;
+		xicon.set("set_type", scope.create_function(|_, (win, name): (Window, String)| {
+			let win_type = <WindowType as clap::ValueEnum>::from_str(&name, true)
+				.map_err(mlua::Error::RuntimeError)?;
+			set_type(conn, win, &[win_type]).map_err(lua_error)
+		})?)?;
+		xicon.set("set_geometry", scope.create_function(|_, (win, geometry): (Window, String)| {
+			let aux = geometry_aux(conn, &Area::from(screen), win, Some(&geometry), None, false, None)
+				.map_err(lua_error)?;
+			set_geometry(conn, win, &aux, false).map_err(lua_error)
+		})?)?;
+		xicon.set("set_title", scope.create_function(|_, (win, title): (Window, String)| {
+			set_title(conn, win, &title).map_err(lua_error)
+		})?)?;
+		xicon.set("set_opacity", scope.create_function(|_, (win, opacity): (Window, f64)| {
+			set_opacity(conn, win, opacity.clamp(0.0, 1.0), false).map_err(lua_error)
+		})?)?;
+		xicon.set("match_window", scope.create_function(|_, (win, property): (Window, String)| {
+			let property = parse_match_property(&property).map_err(mlua::Error::RuntimeError)?;
+			match_window(conn, win, &[], false, &Some(property)).map_err(lua_error)
+		})?)?;
+		let globals = lua.globals();
+		globals.set("xicon", xicon)?;
+		globals.set("window", win)?;
+		lua.load(&code)
+			.set_name(script.display().to_string())
+			.exec()
+	}).map_err(|e| anyhow::anyhow!("Failed run script {}: {e}", script.display()))
+}
+
+#[cfg(feature = "lua")]
+#[inline]
+fn lua_error(error: anyhow::Error) -> mlua::Error
+{
+	mlua::Error::RuntimeError(error.to_string())
+}
@@ -0,0 +1,300 @@
+#[cfg(feature = "xpm")]
+use anyhow::anyhow;
+use anyhow::Result;
+use image::RgbaImage;
+
+#[inline]
+pub fn is_xpm(data: &[u8]) -> bool
+{
+	trim_head(data).starts_with(b"/* XPM */")
+}
+
+#[inline]
+pub fn is_xbm(data: &[u8]) -> bool
+{
+	let head = trim_head(data);
+	if !head.starts_with(b"#define") {
+		return false;
+	}
+	let line = head.split(|b| *b == b'\n').next().unwrap_or_default();
+	String::from_utf8_lossy(line)
+		.split_whitespace()
+		.nth(1)
+		.is_some_and(|name| name.ends_with("_width"))
+}
+
+#[inline]
+fn trim_head(data: &[u8]) -> &[u8]
+{
+	let start = data.iter()
+		.position(|b| !b.is_ascii_whitespace())
+		.unwrap_or(data.len());
+	&data[start..]
+}
+
+#[cfg(not(feature = "xpm"))]
+#[inline]
+pub fn load_xpm(_data: &[u8]) -> Result<RgbaImage>
+{
+	Err(anyhow::anyhow!("XPM support not compiled in"))
+}
+
+#[cfg(not(feature = "xpm"))]
+#[inline]
+pub fn load_xbm(_data: &[u8]) -> Result<RgbaImage>
+{
+	Err(anyhow::anyhow!("XBM support not compiled in"))
+}
+
+#[cfg(feature = "xpm")]
+pub fn load_xpm(data: &[u8]) -> Result<RgbaImage>
+{
+	const MAX_SIZE: usize = 4096;
+
+	let text = String::from_utf8_lossy(data);
+	let mut strings = c_strings(&text).into_iter();
+	let values = strings.next()
+		.ok_or_else(|| anyhow!("Invalid xpm: no values"))?;
+	let values: Vec<usize> = values.split_whitespace()
+		.take(4)
+		.map(|v| v.parse())
+		.collect::<Result<_, _>>()
+		.map_err(|_| anyhow!("Invalid xpm values: {values}"))?;
+	let [width, height, colors, cpp] = values[..] else {
+		return Err(anyhow!("Invalid xpm: incomplete values"));
+	};
+	if width == 0 || height == 0 || width > MAX_SIZE || height > MAX_SIZE {
+		return Err(anyhow!("Invalid xpm size: {width}x{height}"));
+	}
+	let row_length = width.checked_mul(cpp)
+		.ok_or_else(|| anyhow!("Invalid xpm: {cpp} chars per pixel"))?;
+
+	let mut palette = vec![];
+	for _ in 0..colors {
+		let line = strings.next()
+			.ok_or_else(|| anyhow!("Invalid xpm: missing colors"))?;
+		let key = line.get(..cpp)
+			.ok_or_else(|| anyhow!("Invalid xpm color: {line}"))?;
+		let color = xpm_color(&line[cpp..])
+			.ok_or_else(|| anyhow!("Invalid xpm color: {line}"))?;
+		palette.push((key.to_owned(), color));
+	}
+
+	// check pixels before allocating with the size in header
+	let lines: Vec<String> = strings.take(height).collect();
+	if lines.len() < height {
+		return Err(anyhow!("Invalid xpm: expect {height} pixel lines, got {}", lines.len()));
+	}
+	if let Some(line) = lines.iter().find(|line| line.len() < row_length) {
+		return Err(anyhow!("Invalid xpm pixels: {line}"));
+	}
+	let mut image = RgbaImage::new(u32::try_from(width)?, u32::try_from(height)?);
+	for (y, line) in lines.iter().enumerate() {
+		for x in 0..width {
+			let key = line.get(x * cpp..(x + 1) * cpp)
+				.ok_or_else(|| anyhow!("Invalid xpm pixels: {line}"))?;
+			let (_, color) = palette.iter()
+				.find(|(k, _)| k == key)
+				.ok_or_else(|| anyhow!("Undefined xpm color: {key}"))?;
+			image.put_pixel(x as u32, y as u32, (*color).into());
+		}
+	}
+	Ok(image)
+}
+
+/// content of every double quoted string in order
+#[cfg(feature = "xpm")]
+fn c_strings(text: &str) -> Vec<String>
+{
+	let mut strings = vec![];
+	let mut chars = text.chars();
+	while let Some(ch) = chars.next() {
+		match ch {
+			'/' if chars.as_str().starts_with('*') => {
+				// skip comment, quotes may exists in it
+				let rest = chars.as_str();
+				let end = rest.find("*/").map_or(rest.len(), |i| i + 2);
+				chars = rest[end..].chars();
+			}
+			'"' => {
+				let mut string = String::new();
+				while let Some(ch) = chars.next() {
+					match ch {
+						'"' => break,
+						'\\' => if let Some(ch) = chars.next() {
+							string.push(ch);
+						}
+						_ => string.push(ch),
+					}
+				}
+				strings.push(string);
+			}
+			_ => {}
+		}
+	}
+	strings
+}
+
+/// color for the "c" key, or fallback to the grayscale/mono ones
+#[cfg(feature = "xpm")]
+fn xpm_color(spec: &str) -> Option<[u8; 4]>
+{
+	const KEYS: [&str; 5] = ["c", "g", "g4", "m", "s"];
+	let mut colors: Vec<(&str, String)> = vec![];
+	for token in spec.split_whitespace() {
+		if KEYS.contains(&token) {
+			colors.push((token, String::new()));
+		} else if let Some((_, value)) = colors.last_mut() {
+			if !value.is_empty() {
+				value.push(' ');
+			}
+			value.push_str(token);
+		} else {
+			return None;
+		}
+	}
+	for key in ["c", "g", "g4", "m"] {
+		if let Some((_, value)) = colors.iter().find(|(k, _)| *k == key) {
+			return parse_color(value);
+		}
+	}
+	None
+}
+
+#[cfg(feature = "xpm")]
+fn parse_color(value: &str) -> Option<[u8; 4]>
+{
+	if let Some(hex) = value.strip_prefix('#') {
+		// #RGB, #RRGGBB or #RRRRGGGGBBBB, keep the high byte of each
+		let digits = hex.len() / 3;
+		if digits == 0 || hex.len() % 3 != 0 || digits > 4 {
+			return None;
+		}
+		let mut rgba = [0, 0, 0, 255];
+		for (i, channel) in rgba.iter_mut().take(3).enumerate() {
+			let component = hex.get(i * digits..(i + 1) * digits)?;
+			let value = u16::from_str_radix(component, 16).ok()?;
+			*channel = match digits {
+				1 => (value * 17) as u8,
+				2 => value as u8,
+				_ => (value >> ((digits - 2) * 4)) as u8,
+			};
+		}
+		return Some(rgba);
+	}
+	let rgba = match value.to_ascii_lowercase().replace(' ', "").as_str() {
+		"none" => [0, 0, 0, 0],
+		"black" => [0, 0, 0, 255],
+		"white" => [255, 255, 255, 255],
+		"red" => [255, 0, 0, 255],
+		"green" => [0, 255, 0, 255],
+		"blue" => [0, 0, 255, 255],
+		"yellow" => [255, 255, 0, 255],
+		"cyan" => [0, 255, 255, 255],
+		"magenta" => [255, 0, 255, 255],
+		"gray" | "grey" => [190, 190, 190, 255],
+		"lightgray" | "lightgrey" => [211, 211, 211, 255],
+		"darkgray" | "darkgrey" => [169, 169, 169, 255],
+		_ => return None,
+	};
+	Some(rgba)
+}
+
+/// set bits are black, others are transparent
+#[cfg(feature = "xpm")]
+pub fn load_xbm(data: &[u8]) -> Result<RgbaImage>
+{
+	let text = String::from_utf8_lossy(data);
+	let mut width = None;
+	let mut height = None;
+	for line in text.lines() {
+		let mut tokens = line.split_whitespace();
+		if tokens.next() != Some("#define") {
+			continue;
+		}
+		if let (Some(name), Some(value)) = (tokens.next(), tokens.next()) {
+			if name.ends_with("_width") {
+				width = value.parse::<u32>().ok();
+			} else if name.ends_with("_height") {
+				height = value.parse::<u32>().ok();
+			}
+		}
+	}
+	let (Some(width), Some(height)) = (width, height) else {
+		return Err(anyhow!("Invalid xbm: missing width or height"));
+	};
+	let body = text.find('{')
+		.and_then(|start| text[start + 1..].find('}').map(|end| &text[start + 1..start + 1 + end]))
+		.ok_or_else(|| anyhow!("Invalid xbm: missing bits"))?;
+	let bytes: Vec<u8> = body.split(',')
+		.map(str::trim)
+		.filter(|v| !v.is_empty())
+		.map(|v| u8::from_str_radix(v.trim_start_matches("0x").trim_start_matches("0X"), 16))
+		.collect::<Result<_, _>>()
+		.map_err(|e| anyhow!("Invalid xbm bits: {e}"))?;
+	let row_bytes = width.div_ceil(8) as usize;
+	if bytes.len() < row_bytes * height as usize {
+		return Err(anyhow!("Invalid xbm: expect {} bytes, got {}", row_bytes * height as usize, bytes.len()));
+	}
+	let mut image = RgbaImage::new(width, height);
+	for (x, y, pixel) in image.enumerate_pixels_mut() {
+		let byte = bytes[y as usize * row_bytes + x as usize / 8];
+		if byte & (1 << (x % 8)) != 0 {
+			pixel.0 = [0, 0, 0, 255];
+		}
+	}
+	Ok(image)
+}
+
+#[cfg(test)]
+mod test {
+	use crate::xpm::{is_xbm, is_xpm};
+
+	#[test]
+	fn test_detect()
+	{
+		assert!(is_xpm(b"/* XPM */\nstatic char *icon[] = {"));
+		assert!(!is_xpm(b"static char *icon[] = {"));
+		assert!(is_xbm(b"#define icon_width 8\n#define icon_height 2\n"));
+		assert!(!is_xbm(b"#define ICON_SIZE 8\n"));
+	}
+
+	#[test]
+	#[cfg(feature = "xpm")]
+	fn test_load_xpm()
+	{
+		let image = crate::xpm::load_xpm(br##"/* XPM */
+static char * icon[] = {
+/* "width height colors cpp" */
+"3 2 3 1",
+". c None",
+"# c #FF0000",
+"g c light gray s background",
+".#g",
+"g#.",
+};"##).unwrap();
+		assert_eq!(image.dimensions(), (3, 2));
+		assert_eq!(image.get_pixel(0, 0).0, [0, 0, 0, 0]);
+		assert_eq!(image.get_pixel(1, 0).0, [255, 0, 0, 255]);
+		assert_eq!(image.get_pixel(2, 0).0, [211, 211, 211, 255]);
+		assert_eq!(image.get_pixel(0, 1).0, [211, 211, 211, 255]);
+		assert!(crate::xpm::load_xpm(br#"/* XPM */ { "65535 65535 1 1", ". c None", "..." };"#).is_err());
+		assert!(crate::xpm::load_xpm(br#"/* XPM */ { "3 2 1 1", ". c None", "...", ".." };"#).is_err());
+		assert!(crate::xpm::load_xpm(br#"/* XPM */ { "3 1 1 1", ". c None", "..." };"#).is_ok());
+	}
+
+	#[test]
+	#[cfg(feature = "xpm")]
+	fn test_load_xbm()
+	{
+		let image = crate::xpm::load_xbm(b"#define icon_width 10
+#define icon_height 2
+static unsigned char icon_bits[] = {
+   0x01, 0x02, 0x80, 0x00 };").unwrap();
+		assert_eq!(image.dimensions(), (10, 2));
+		assert_eq!(image.get_pixel(0, 0).0, [0, 0, 0, 255]);
+		assert_eq!(image.get_pixel(1, 0).0, [0, 0, 0, 0]);
+		assert_eq!(image.get_pixel(9, 0).0, [0, 0, 0, 255]);
+		assert_eq!(image.get_pixel(7, 1).0, [0, 0, 0, 255]);
+	}
+}